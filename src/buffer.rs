@@ -0,0 +1,226 @@
+use std::{
+    borrow::Cow,
+    cmp::max,
+    fmt,
+    iter::{repeat_n, FromIterator},
+    ops::{Bound, RangeBounds},
+};
+
+// The smallest gap to open up when inserting into a full buffer
+const MIN_GAP: usize = 4096;
+
+// A gap buffer. Edits move the gap to where they happen, so repeated edits around the caret
+// only cost the distance moved, instead of shifting everything behind them.
+#[derive(Clone, Default)]
+pub struct Buffer {
+    data: Vec<u8>,
+    gap_start: usize,
+    gap_end: usize,
+}
+
+impl Buffer {
+    pub fn new() -> Buffer {
+        Buffer::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len() - self.gap_len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<u8> {
+        if index < self.gap_start {
+            Some(self.data[index])
+        } else {
+            self.data.get(index + self.gap_len()).cloned()
+        }
+    }
+
+    // The content before and after the gap, like `VecDeque::as_slices`
+    pub fn as_slices(&self) -> (&[u8], &[u8]) {
+        (&self.data[..self.gap_start], &self.data[self.gap_end..])
+    }
+
+    // Moves the gap to the end, like `VecDeque::make_contiguous`
+    pub fn make_contiguous(&mut self) -> &[u8] {
+        self.move_gap(self.len());
+        &self.data[..self.gap_start]
+    }
+
+    // Only copies when the range spans the gap. Panics on out-of-bounds ranges, like slicing.
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> Cow<'_, [u8]> {
+        let (start, end) = self.resolve(range);
+        let gap_len = self.gap_len();
+
+        if end <= self.gap_start {
+            Cow::Borrowed(&self.data[start..end])
+        } else if start >= self.gap_start {
+            Cow::Borrowed(&self.data[start + gap_len..end + gap_len])
+        } else {
+            let mut bytes = Vec::with_capacity(end - start);
+            bytes.extend_from_slice(&self.data[start..self.gap_start]);
+            bytes.extend_from_slice(&self.data[self.gap_end..end + gap_len]);
+            Cow::Owned(bytes)
+        }
+    }
+
+    // Replaces `range` with `new`. Panics on out-of-bounds ranges, like `Vec::splice`.
+    pub fn splice<R: RangeBounds<usize>>(&mut self, range: R, new: &[u8]) {
+        let (start, end) = self.resolve(range);
+
+        self.move_gap(start);
+        self.gap_end += end - start;
+
+        if self.gap_len() < new.len() {
+            self.grow(new.len());
+        }
+
+        self.data[self.gap_start..self.gap_start + new.len()].copy_from_slice(new);
+        self.gap_start += new.len();
+    }
+
+    fn gap_len(&self) -> usize {
+        self.gap_end - self.gap_start
+    }
+
+    fn resolve<R: RangeBounds<usize>>(&self, range: R) -> (usize, usize) {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.len(),
+        };
+
+        assert!(
+            start <= end && end <= self.len(),
+            "range {}..{} out of bounds for a buffer of {} bytes",
+            start,
+            end,
+            self.len()
+        );
+
+        (start, end)
+    }
+
+    fn move_gap(&mut self, index: usize) {
+        if index < self.gap_start {
+            let distance = self.gap_start - index;
+            self.data
+                .copy_within(index..self.gap_start, self.gap_end - distance);
+            self.gap_start -= distance;
+            self.gap_end -= distance;
+        } else if index > self.gap_start {
+            let distance = index - self.gap_start;
+            self.data
+                .copy_within(self.gap_end..self.gap_end + distance, self.gap_start);
+            self.gap_start += distance;
+            self.gap_end += distance;
+        }
+    }
+
+    // Grows geometrically, so a series of inserts stays amortized constant
+    fn grow(&mut self, needed: usize) {
+        let extra = max(needed, max(self.len(), MIN_GAP));
+        self.data
+            .splice(self.gap_end..self.gap_end, repeat_n(0, extra));
+        self.gap_end += extra;
+    }
+}
+
+impl From<Vec<u8>> for Buffer {
+    fn from(data: Vec<u8>) -> Buffer {
+        let len = data.len();
+        Buffer {
+            data,
+            gap_start: len,
+            gap_end: len,
+        }
+    }
+}
+
+impl FromIterator<u8> for Buffer {
+    fn from_iter<I: IntoIterator<Item = u8>>(iter: I) -> Buffer {
+        Buffer::from(iter.into_iter().collect::<Vec<u8>>())
+    }
+}
+
+impl fmt::Debug for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (front, back) = self.as_slices();
+        f.debug_list().entries(front.iter().chain(back)).finish()
+    }
+}
+
+impl PartialEq<[u8]> for Buffer {
+    fn eq(&self, other: &[u8]) -> bool {
+        let (front, back) = self.as_slices();
+        front.len() + back.len() == other.len()
+            && front == &other[..front.len()]
+            && back == &other[front.len()..]
+    }
+}
+
+impl PartialEq<Vec<u8>> for Buffer {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        self == other.as_slice()
+    }
+}
+
+impl PartialEq for Buffer {
+    fn eq(&self, other: &Buffer) -> bool {
+        self.len() == other.len() && self.slice(..) == other.slice(..)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+
+    #[quickcheck]
+    fn test_splice(initial: Vec<u8>, edits: Vec<(usize, usize, Vec<u8>)>) -> bool {
+        let mut expected = initial.clone();
+        let mut buffer = Buffer::from(initial);
+
+        for (a, b, new) in edits {
+            let len = expected.len() + 1;
+            let (start, end) = (a % len, b % len);
+            let (start, end) = (start.min(end), start.max(end));
+
+            expected.splice(start..end, new.iter().cloned());
+            buffer.splice(start..end, &new);
+
+            if buffer != expected || buffer.len() != expected.len() {
+                return false;
+            }
+        }
+
+        (0..=expected.len()).all(|i| buffer.get(i) == expected.get(i).cloned())
+    }
+
+    #[test]
+    fn test_slice_across_gap() {
+        let mut buffer: Buffer = (0..8).collect();
+        buffer.splice(4..4, &[0xff]);
+
+        assert_eq!(
+            buffer.as_slices(),
+            (&[0, 1, 2, 3, 0xff][..], &[4, 5, 6, 7][..])
+        );
+        assert!(matches!(buffer.slice(1..3), Cow::Borrowed(_)));
+        assert_eq!(&*buffer.slice(3..=5), &[3, 0xff, 4]);
+        assert_eq!(buffer, vec![0, 1, 2, 3, 0xff, 4, 5, 6, 7]);
+
+        assert_eq!(buffer.make_contiguous(), &[0, 1, 2, 3, 0xff, 4, 5, 6, 7]);
+        assert!(matches!(buffer.slice(3..=5), Cow::Borrowed(_)));
+        assert_eq!(buffer.get(4), Some(0xff));
+    }
+}
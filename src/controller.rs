@@ -1,28 +1,77 @@
-use std::{convert::TryFrom, mem::swap};
+use std::{
+    cmp::{max, min},
+    collections::HashMap,
+    convert::TryFrom,
+    env, fmt,
+    fs::{self, canonicalize, read_to_string, write},
+    io::{Result as IoResult, Write},
+    iter::successors,
+    mem::swap,
+    path::Path,
+    process,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
+use regex::bytes::{Regex, RegexBuilder};
 use termion::{self, event::Key};
 
 use crate::{
-    model::{Caret, Model},
-    utils::{read_from_clipboard, save_to_clipboard},
+    model::{Caret, Model, READ_CHUNK},
+    utils::{
+        count_matches, describe_copy, edit_with, filter_through, find_next, find_prev,
+        has_system_clipboard, osc52_copy, read_from_clipboard, save_to_clipboard, xxd_dump,
+    },
     view::*,
     vim::*,
     UsizeMax,
 };
 
+// Leaves the terminal to another program if called with true, and takes it back with false
+pub type Suspend = Box<dyn FnMut(bool) -> IoResult<()>>;
+
+// Larger copies must be forced, the hex text is twice as large and may stall the clipboard
+const CLIPBOARD_LIMIT: usize = 16 * 1024 * 1024;
+
+// Where `Ctrl-c` and `Ctrl-v` copy to and paste from
+enum Clipboard {
+    // Not looked for yet, as connecting to X may take a moment
+    Detect,
+    System,
+    // Copies through the terminal, which can't be read back. Pastes what was copied last.
+    Osc52(Vec<u8>),
+    // Without a system clipboard the bytes are kept here, thus only within this session
+    Session(Vec<u8>),
+}
+
+// Like Vim, the jump list forgets the oldest jumps beyond this
+const JUMPLIST_SIZE: usize = 100;
+
+// Offsets listed by `:list`, the status line has no room for more
+const LIST_MAX: usize = 16;
+
 #[derive(Clone, Debug)]
 pub enum Msg {
     Open(String),
     Quit,
     QuitWithoutSaving,
     Save,
-    SaveAs(String),
+    SaveAs(String, bool),
     SaveAndQuit,
+    // Writes the selection or everything as a dump, with the given bytes per line
+    Export(String, Option<usize>, bool),
+    // Appends the selection or everything to an existing file
+    Append(String),
+    // Edits the buffer in `$HEXEDITOR` or `$EDITOR`
+    EditExternal,
     // ---
     Byte(u8),
+    Nibble(u8, bool),
     Move(Direction),
     Switch(Option<InputMode>),
     Delete(Option<Movement>),
+    // Applies an operator to the bytes from the caret to the target of the motion, repeated
+    // `count` times
+    Operate(Operator, Motion, Option<usize>),
     // ---
     ToNormal,
     ToInsert(Option<usize>),
@@ -33,15 +82,131 @@ pub enum Msg {
     // ---
     Yank,
     Paste(Option<Movement>),
-    ClipboardCopy,
+    // Forced to copy beyond CLIPBOARD_LIMIT
+    ClipboardCopy(bool),
     ClipboardPaste,
+    Filter(String),
+    Sort(bool),
+    Reverse,
+    Swap(usize),
+    // Inserts the seconds since the epoch at the caret, in 4 or 8 bytes
+    Timestamp(usize),
+    // Upper case if set, only ASCII letters change
+    Case(bool),
+    // ---
+    // `None` matches any byte
+    Search(Vec<Option<u8>>),
+    // Searches the bytes with a regular expression, `n` and `N` step through the matches
+    Grep(String),
+    // Reports the offsets of all matches in the status line
+    List(Vec<Option<u8>>),
+    // Sets the note on the byte under the caret, `None` removes it
+    Note(Option<String>),
+    SearchNext(Movement),
+    Find(u8),
+    FindNext,
+    Jump(Movement),
     // ---
     Undo,
     Redo,
     // ---
+    Set(Setting),
+    // Applies the valid settings and reports the first invalid one
+    SetMany(Vec<Result<Setting, &'static str>>),
+    Map(Key, String),
+    // ---
+    Info,
     Show(String),
     Redraw,
+    // Clears the terminal before redrawing, for when stray output garbled it
+    Repaint,
     Resize((u16, u16)),
+    // Moves the caret to the byte at a terminal cell
+    Click(u16, u16),
+    // Extends the selection over the word at the caret, like `iw`
+    SelectWord,
+    // Selects from the anchor to the end, both inclusive
+    SelectRange(usize, usize),
+    // Opens or closes the help, which is scrolled by lines
+    Help(bool),
+    ScrollHelp(isize),
+}
+
+#[derive(Clone, Debug)]
+pub enum Setting {
+    Snap(bool),
+    Caret(Option<CaretStyle>),
+    Wrapscan(bool),
+    Nibble(bool),
+    NumberWidth(u16),
+    ErrorBell(bool),
+    Tildes(bool),
+    BackupCopy(bool),
+    FileFormat(LineEnding),
+    Clipboard(ClipboardKind),
+    ScrollOff(usize),
+    UndoLevels(usize),
+    Undo(bool),
+    IncSearch(bool),
+    IgnoreCase(bool),
+    ShowCmd(bool),
+    // Record size, 0 for none
+    Record(usize),
+    Entropy(bool),
+    LastStatus(bool),
+    SwapFile(bool),
+    // Upper case hex digits if set
+    HexCase(bool),
+    CursorLine(bool),
+    // Only within a row, `None` for no column
+    ColorColumn(Option<usize>),
+    // Of the character pane
+    Encoding(Encoding),
+    // Draws wide glyphs over two cells of the character pane
+    Wrap(bool),
+    MatchByte(bool),
+    // Lets the caret rest one past the last byte, to insert or replace at the end
+    VirtualEdit(bool),
+    Signed(bool),
+    // Byte order of the values written by commands like `:timestamp`
+    Endian(Endian),
+    Mouse(bool),
+    RelativeNumber(bool),
+    // Shows the offset column
+    Number(bool),
+    AutoWrite(bool),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+// The clipboard chosen with `:set clipboard`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClipboardKind {
+    System,
+    // The terminal's, through OSC 52 escape sequences
+    Osc52,
+}
+
+// Line ending inserted by Enter in ASCII insert mode
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineEnding {
+    Unix,
+    Dos,
+    Mac,
+}
+
+impl LineEnding {
+    pub fn line_ending(self) -> &'static [u8] {
+        match self {
+            LineEnding::Unix => b"\n",
+            LineEnding::Dos => b"\r\n",
+            LineEnding::Mac => b"\r",
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -50,6 +215,26 @@ pub enum Movement {
     Right,
 }
 
+#[derive(Clone, Copy, Debug)]
+pub enum Operator {
+    Delete,
+    Yank,
+}
+
+// Targets of an operator. Words are runs of nonzero bytes, separated by zeros.
+#[derive(Clone, Copy, Debug)]
+pub enum Motion {
+    // The byte under the caret, like `l`
+    Byte,
+    WordStart,
+    WordEnd,
+    RowStart,
+    RowEnd,
+    BufferEnd,
+    // The whole row, for doubled operators like `dd`
+    Row,
+}
+
 #[derive(Clone, Debug)]
 pub enum Direction {
     Left,
@@ -58,6 +243,9 @@ pub enum Direction {
     Down,
     //Start,
     Offset(usize),
+    Relative(isize),
+    // Percentage of the buffer length, 0 to 100
+    Percent(usize),
     //End,
     Newline,
     Revert,
@@ -78,12 +266,75 @@ impl TryFrom<Key> for Direction {
     }
 }
 
+fn same_file(a: &str, b: &str) -> bool {
+    match (canonicalize(a), canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
 pub struct Controller {
     pub state: VimState,
     pub model: Model,
     pub view: View,
     mode: InputMode,
     yank: Option<Vec<u8>>,
+    clipboard: Clipboard,
+    search: Option<Vec<Option<u8>>>,
+    // Replaces the hex pattern for `n` and `N` until the next `/`
+    grep: Option<Regex>,
+    // Target of the last `f`, repeated by `;`
+    find: Option<u8>,
+    keymap: HashMap<Key, String>,
+    // Offsets jumped away from, `jump` points behind the newest unless stepping through them
+    jumps: Vec<usize>,
+    jump: usize,
+    count: Option<usize>,
+    // Keys of the command being typed, like `3d`, shown with `:set showcmd on`
+    pending: String,
+    showcmd: bool,
+    // Bytes typed during a counted insert, replayed when leaving insert mode
+    repeat: Option<(usize, Vec<u8>)>,
+    // Why the last command failed to write, for `source` to stop at
+    failure: Option<String>,
+    snap: bool,
+    wrapscan: bool,
+    // Moves to the first match while the search is typed
+    incsearch: bool,
+    // Matches ASCII letters in the search pattern in either case
+    ignorecase: bool,
+    endian: Endian,
+    // Decodes the byte under the caret as signed in the status line
+    signed: bool,
+    // Where the search being typed started, to return to if cancelled
+    search_start: Option<usize>,
+    nibble: bool,
+    errorbell: bool,
+    // Clicks are ignored if off. The terminal still reports them, see `--no-mouse`.
+    mouse: bool,
+    // Saves a modified buffer before opening another file, instead of refusing to
+    autowrite: bool,
+    fileformat: LineEnding,
+    // Trace of messages and transitions, see `--log`
+    log: Option<Box<dyn Write>>,
+    // Not set without a terminal, e.g. in tests
+    suspend: Option<Suspend>,
+}
+
+// Appends a line with the seconds since the epoch, if logging. The entry is only formatted then.
+fn write_log(log: &mut Option<Box<dyn Write>>, entry: fmt::Arguments) {
+    if let Some(log) = log {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let _ = writeln!(
+            log,
+            "{}.{:03} {}",
+            time.as_secs(),
+            time.subsec_millis(),
+            entry
+        );
+    }
 }
 
 impl Controller {
@@ -94,56 +345,379 @@ impl Controller {
             view,
             mode: InputMode::Hex,
             yank: None,
+            clipboard: Clipboard::Detect,
+            search: None,
+            grep: None,
+            find: None,
+            keymap: HashMap::new(),
+            jumps: vec![],
+            jump: 0,
+            count: None,
+            pending: String::new(),
+            showcmd: false,
+            repeat: None,
+            failure: None,
+            snap: false,
+            wrapscan: true,
+            incsearch: false,
+            ignorecase: false,
+            endian: Endian::Little,
+            signed: false,
+            search_start: None,
+            nibble: false,
+            errorbell: false,
+            mouse: true,
+            autowrite: false,
+            fileformat: LineEnding::Unix,
+            log: None,
+            suspend: None,
+        }
+    }
+
+    pub fn set_log(&mut self, log: Box<dyn Write>) {
+        self.log = Some(log);
+    }
+
+    // Picks the clipboard on first use. Through SSH the terminal's is the one at hand, otherwise
+    // it falls back to the session clipboard if there is no system clipboard.
+    fn detect_clipboard(&mut self) {
+        if let Clipboard::Detect = self.clipboard {
+            self.clipboard = if env::var_os("SSH_CONNECTION").is_some() {
+                Clipboard::Osc52(vec![])
+            } else {
+                Self::system_clipboard()
+            };
+        }
+    }
+
+    fn system_clipboard() -> Clipboard {
+        if has_system_clipboard() {
+            Clipboard::System
+        } else {
+            Clipboard::Session(vec![])
+        }
+    }
+
+    pub fn set_suspend(&mut self, suspend: Suspend) {
+        self.suspend = Some(suspend);
+    }
+
+    // Hands the buffer to `editor` through a temporary file and takes back what it saved there.
+    // The result replaces the buffer as a single, undoable edit.
+    pub fn edit_external(&mut self, editor: &str) {
+        let path = env::temp_dir().join(format!("xim-external-{}", process::id()));
+        if let Err(e) = fs::write(&path, &*self.model.bytes(..)) {
+            self.view
+                .status_view
+                .set_body(&format!("could not write temporary file ({})", e));
+            return;
+        }
+
+        if let Some(ref mut suspend) = self.suspend {
+            if let Err(error) = suspend(true) {
+                eprintln!("{}", error);
+            }
+        }
+        let result = edit_with(editor, &path).and_then(|_| {
+            fs::read(&path)
+                .map_err(|e| format!("could not read back \"{}\" ({})", path.display(), e))
+        });
+        if let Some(ref mut suspend) = self.suspend {
+            if let Err(error) = suspend(false) {
+                eprintln!("{}", error);
+            }
+        }
+        let _ = fs::remove_file(&path);
+
+        match result {
+            Ok(ref bytes) if self.model.bytes(..) == bytes.as_slice() => {
+                self.view.status_view.set_body("no changes");
+            }
+            Ok(bytes) => {
+                let index = self.model.get_index();
+                if let Err(e) = self.model.edit(0, self.model.len(), &bytes) {
+                    self.view
+                        .status_view
+                        .set_body(&format!("could not replace buffer ({})", e));
+                    return;
+                }
+
+                self.update(Msg::ToNormal);
+                self.model.set_index(index);
+                self.view.hex_view.scroll_to(self.model.get_index());
+                self.view.status_view.set_body(&format!(
+                    "{} bytes read back from \"{}\"",
+                    bytes.len(),
+                    editor
+                ));
+                self.snapshot();
+            }
+            Err(ref e) => {
+                self.view.status_view.set_body(e);
+            }
         }
+
+        self.update(Msg::Repaint);
     }
 
     // Opening, Saving, etc.
 
     pub fn open(&mut self, path: &str) {
-        match self.model.open(path) {
+        let view = &mut self.view;
+        let mut spinner = ['|', '/', '-', '\\'].iter().cycle();
+        let mut loading = false;
+
+        let result = self.model.open_with_progress(path, |read, size| {
+            // Files read in a single chunk are there before anybody would notice
+            if size <= READ_CHUNK {
+                return;
+            }
+
+            loading = true;
+            view.status_view.set_body(&format!(
+                "loading \"{}\" ({} bytes) {} {}%",
+                path,
+                size,
+                // Safe-from-panic: The spinner cycles endlessly
+                spinner.next().unwrap(),
+                min(read as u128 * 100 / size as u128, 100)
+            ));
+            if let Err(error) = view.draw_status() {
+                eprintln!("{}", error);
+            }
+        });
+
+        if loading {
+            self.view.status_view.set_body("");
+        }
+
+        match result {
             Ok(_) => self.view.status_view.set_head(path),
             Err(e) => self.view.status_view.set_head(&format!("error: {}", e)),
         }
+
+        if self.model.swapfile {
+            if let Err(msg) = self.model.lock() {
+                self.view.status_view.set_body(&msg);
+            }
+        }
     }
 
     pub fn save(&mut self) -> bool {
-        match self.model.save() {
+        let path = self.model.path.clone();
+        self.save_as(path)
+    }
+
+    // Reports the size and how long it took, which tells how slow the storage is for big files
+    pub fn save_as(&mut self, path: String) -> bool {
+        let start = Instant::now();
+
+        match self.model.save_as(&path) {
             Ok(_) => {
-                self.view
-                    .status_view
-                    .set_body(&format!("\"{}\" saved", self.model.path));
+                self.view.status_view.set_body(&format!(
+                    "\"{}\" {} bytes written in {:.2}s",
+                    &path,
+                    self.model.len(),
+                    start.elapsed().as_secs_f64()
+                ));
                 true
             }
             Err(error) => {
-                self.view.status_view.set_body(&format!(
-                    "could not save \"{}\": {}",
-                    self.model.path, error
-                ));
+                self.fail(&format!("could not save \"{}\": {}", &path, error));
                 false
             }
         }
     }
 
-    pub fn save_as(&mut self, path: String) -> bool {
-        match self.model.save_as(&path) {
-            Ok(_) => {
-                self.view
-                    .status_view
-                    .set_body(&format!("\"{}\" saved", &path));
-                true
+    // The half-open range from the caret to the target of `motion`, repeated `count` times. Like
+    // in Vim, `$` and doubled operators take that many rows, `0` and `G` ignore the count.
+    fn motion_range(&self, motion: Motion, count: usize) -> (usize, usize) {
+        let index = self.model.get_index();
+        let len = self.model.len();
+        let row_start = index - index % BYTES_PER_ROW;
+        let rows_end = min(
+            row_start.saturating_add(count.saturating_mul(BYTES_PER_ROW)),
+            len,
+        );
+
+        // Stops early at the end of the buffer, where the word motions get stuck
+        let repeat = |step: &dyn Fn(usize) -> usize| {
+            let mut target = index;
+            for _ in 0..count {
+                let next = step(target);
+                if next == target {
+                    break;
+                }
+                target = next;
             }
-            Err(error) => {
-                self.view.status_view.set_body(&format!(
-                    "could not save \"{}\": {}",
-                    self.model.path, error
-                ));
-                false
+            target
+        };
+
+        match motion {
+            Motion::Byte => (index, min(index.saturating_add(count), len)),
+            Motion::WordStart => (index, repeat(&|i| self.word_start_after(i))),
+            Motion::WordEnd => (index, repeat(&|i| self.word_end_after(i)) + 1),
+            Motion::RowStart => (row_start, index),
+            Motion::RowEnd => (index, rows_end),
+            Motion::BufferEnd => (index, len),
+            Motion::Row => (row_start, rows_end),
+        }
+    }
+
+    // Start of the next word after `index`, or the end of the buffer
+    fn word_start_after(&self, index: usize) -> usize {
+        let mut i = index;
+        while matches!(self.model.byte_at(i), Some(byte) if byte != 0) {
+            i += 1;
+        }
+        while self.model.byte_at(i) == Some(0) {
+            i += 1;
+        }
+        i
+    }
+
+    // Last byte of the word after `index`, or of the buffer
+    fn word_end_after(&self, index: usize) -> usize {
+        let last = self.model.len().saturating_sub(1);
+        let mut i = index + 1;
+        while self.model.byte_at(i) == Some(0) {
+            i += 1;
+        }
+        while matches!(self.model.byte_at(i + 1), Some(byte) if byte != 0) {
+            i += 1;
+        }
+        min(i, last)
+    }
+
+    // The inclusive range of the word around `index`. Runs of zeros count as words here, so
+    // the gaps between nonzero runs can be selected as well.
+    fn word_around(&self, index: usize) -> (usize, usize) {
+        let zero = self.model.byte_at(index) == Some(0);
+        let same = |i| matches!(self.model.byte_at(i), Some(byte) if (byte == 0) == zero);
+
+        let mut start = index;
+        while start > 0 && same(start - 1) {
+            start -= 1;
+        }
+        let mut end = index;
+        while same(end + 1) {
+            end += 1;
+        }
+        (start, end)
+    }
+
+    // Summarizes the buffer, computed in a single pass on every call
+    pub fn info(&mut self) -> String {
+        self.model.make_contiguous();
+        let buffer = &*self.model.bytes(..);
+
+        let mut info = format!("\"{}\"", self.model.path);
+        if self.model.is_modified() {
+            info.push_str(" [Modified]");
+        }
+        info.push_str(&format!(
+            " {} bytes, offset 0x{:x}",
+            buffer.len(),
+            self.model.get_index()
+        ));
+
+        if let Some((start, end)) = self.selection() {
+            info.push_str(&format!(", {} selected", end - start + 1));
+        }
+
+        if !buffer.is_empty() {
+            let mut histogram = [0usize; 256];
+            for &byte in buffer {
+                histogram[byte as usize] += 1;
+            }
+
+            // Ties go to the smallest byte
+            let (common, &count) = histogram
+                .iter()
+                .enumerate()
+                .rev()
+                .max_by_key(|&(_, count)| count)
+                .unwrap();
+            let percent = |count: usize| count as f64 * 100.0 / buffer.len() as f64;
+
+            info.push_str(&format!(
+                ", {:.1}% zero, most common 0x{:02x} ({:.1}%)",
+                percent(histogram[0]),
+                common,
+                percent(count)
+            ));
+        }
+
+        info
+    }
+
+    // Scripting
+
+    // Runs the commands in `path`, one per line. Returns whether to keep running, or the
+    // first failing command unless `force` is set.
+    pub fn source(&mut self, path: &str, force: bool) -> Result<bool, String> {
+        let script =
+            read_to_string(path).map_err(|e| format!("could not read \"{}\": {}", path, e))?;
+
+        for (no, line) in script.lines().enumerate() {
+            // Skip blank lines and comments, allow a leading ':' for familiarity
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('"') {
+                continue;
+            }
+            let cmd = line.strip_prefix(':').unwrap_or(line);
+
+            // Commands may parse but still fail when run, like a `:w` that can't write
+            let error = match Msg::parse(cmd) {
+                Ok(msg) => {
+                    self.failure = None;
+                    if !self.update(msg) {
+                        return Ok(false);
+                    }
+                    self.failure.take()
+                }
+                Err(msg) => Some(format!("{} ({})", msg, cmd)),
+            };
+
+            if let Some(error) = error {
+                let error = format!("{}:{}: {}", path, no + 1, error);
+                if force {
+                    self.view.status_view.set_body(&error);
+                } else {
+                    return Err(error);
+                }
             }
         }
+
+        Ok(true)
     }
 
     // Editing
 
+    pub fn snapshot(&mut self) {
+        self.model.snapshot(self.view.hex_view.get_scroll_start());
+    }
+
+    // Returns the normalized, inclusive bounds of the visual selection
+    pub fn selection(&self) -> Option<(usize, usize)> {
+        match self.model.caret {
+            Caret::Visual(start, end) if !self.model.is_empty() => {
+                let last = self.model.len() - 1;
+                let (start, end) = (start.value(), end.value());
+                Some((min(min(start, end), last), min(max(start, end), last)))
+            }
+            _ => None,
+        }
+    }
+
+    // Returns the half-open range of the selection or, if there is none, the whole buffer
+    pub fn selection_or_all(&self) -> (usize, usize) {
+        match self.selection() {
+            Some((start, end)) => (start, end + 1),
+            None => (0, self.model.len()),
+        }
+    }
+
     pub fn insert(&mut self, value: u8) {
         let index = self.model.get_index();
         if let Err(e) = self.model.edit(index, index, &[value]) {
@@ -164,11 +738,58 @@ impl Controller {
         self.view.hex_view.scroll_to(self.model.get_index());
     }
 
+    // Replaces the selection with `value` and returns to Normal mode, pastes at the caret if
+    // there is no selection
+    pub fn paste_over_selection(&mut self, value: &[u8]) {
+        let (start, end) = match self.selection() {
+            Some(selection) => selection,
+            None => {
+                let index = self.model.get_index();
+                self.paste(index, value);
+                return;
+            }
+        };
+
+        if let Err(e) = self.model.edit(start, end + 1, value) {
+            self.view
+                .status_view
+                .set_body(&format!("could not replace range ({})", e));
+            return;
+        }
+
+        // On the last pasted byte, like in Vim
+        self.model
+            .set_index((start + value.len()).saturating_sub(1));
+        self.update(Msg::ToNormal);
+        self.view.hex_view.scroll_to(self.model.get_index());
+    }
+
+    // Puts the caret between bytes, at the byte it was on. `repeat` is the count of a counted
+    // insert, completed when leaving Insert mode.
+    fn enter_insert(&mut self, repeat: Option<usize>) {
+        self.repeat = repeat.map(|count| (count, vec![]));
+
+        self.model.caret = match self.model.caret {
+            Caret::Index(index) => Caret::Index(index),
+            // From the buffer, as an empty one has a maximum offset of 0 as well
+            Caret::Offset(index) | Caret::Replace(index) | Caret::Visual(_, index) => {
+                Caret::Index(UsizeMax::new(index.value(), self.model.len()))
+            }
+        };
+
+        self.view.status_view.set_body(&format!(
+            "{}-- Insert ({:?}) --{}",
+            termion::style::Bold,
+            self.mode,
+            termion::style::Reset
+        )); // TODO
+    }
+
     pub fn remove_left(&mut self) {
         let index = self.model.get_index();
 
         // If cursor is at end, deleting will move the cursor to the left since the range updates automatically...
-        let end = index == self.model.buffer.len();
+        let end = index == self.model.len();
 
         if let Err(e) = self.model.edit(index.saturating_sub(1), index, &[]) {
             self.view
@@ -191,18 +812,276 @@ impl Controller {
         }
     }
 
+    // Past the last byte, with `virtualedit`, the byte is appended
     pub fn replace(&mut self, value: u8) {
         let index = self.model.get_index();
-        if let Err(e) = self.model.edit(index, index.saturating_add(1), &[value]) {
+        let end = min(index.saturating_add(1), self.model.len());
+        if let Err(e) = self.model.edit(index, end, &[value]) {
             self.view
                 .status_view
                 .set_body(&format!("could not replace value ({})", e));
         }
     }
 
+    // Searching
+
+    // Moves to the first match of the complete bytes typed so far, or back to the start
+    fn preview_search(&mut self, pattern: &str) {
+        let start = match self.search_start {
+            Some(start) if self.incsearch => start,
+            _ => return,
+        };
+
+        // A half typed byte at the end is left out
+        let pattern = pattern.trim_end();
+        let complete = parse_hex_pattern(pattern).or_else(|_| {
+            let mut chars = pattern.chars();
+            chars.next_back();
+            parse_hex_pattern(chars.as_str())
+        });
+
+        let target = match complete {
+            Ok(ref bytes) if !bytes.is_empty() => {
+                self.model.make_contiguous();
+                let buffer = &*self.model.bytes(..);
+                find_next(buffer, bytes, start.saturating_add(1), self.ignorecase)
+                    .or_else(|| {
+                        find_next(buffer, bytes, 0, self.ignorecase).filter(|_| self.wrapscan)
+                    })
+                    .unwrap_or(start)
+            }
+            _ => start,
+        };
+
+        self.model.set_index(target);
+        self.view.hex_view.scroll_to(target);
+        self.view.status_view.set_index(target);
+    }
+
+    // Returns to where the search started, the actual search goes from there
+    fn leave_search(&mut self) {
+        if let Some(start) = self.search_start.take() {
+            self.model.set_index(start);
+            self.view.hex_view.scroll_to(start);
+            self.view.status_view.set_index(start);
+        }
+    }
+
+    pub fn search(&mut self, forward: bool) {
+        if let Some(regex) = self.grep.clone() {
+            self.grep_search(&regex, forward);
+            return;
+        }
+
+        let pattern = match self.search {
+            Some(ref pattern) => pattern.clone(),
+            None => {
+                self.view.status_view.set_body("no previous search pattern");
+                return;
+            }
+        };
+
+        let index = self.model.get_index();
+        self.model.make_contiguous();
+        let buffer = &*self.model.bytes(..);
+
+        let (found, wrapped) = if forward {
+            match find_next(buffer, &pattern, index.saturating_add(1), self.ignorecase) {
+                Some(offset) => (Some(offset), false),
+                None if self.wrapscan => (find_next(buffer, &pattern, 0, self.ignorecase), true),
+                None => (None, false),
+            }
+        } else {
+            match find_prev(buffer, &pattern, index, self.ignorecase) {
+                Some(offset) => (Some(offset), false),
+                None if self.wrapscan => (
+                    find_prev(buffer, &pattern, buffer.len(), self.ignorecase),
+                    true,
+                ),
+                None => (None, false),
+            }
+        };
+
+        // Counted anew each time, as the buffer may have changed since the last search
+        let (current, total) = found.map_or((0, 0), |offset| {
+            count_matches(buffer, &pattern, offset, self.ignorecase)
+        });
+        let pattern = format_hex_pattern(&pattern);
+
+        self.found(
+            index,
+            found,
+            (wrapped, forward),
+            (current, total),
+            &format!("/{}", pattern),
+            &pattern,
+        );
+    }
+
+    // Like `search`, over the matches of a regular expression instead
+    fn grep_search(&mut self, regex: &Regex, forward: bool) {
+        let index = self.model.get_index();
+        self.model.make_contiguous();
+        let starts: Vec<usize> = regex
+            .find_iter(&self.model.bytes(..))
+            .map(|m| m.start())
+            .collect();
+
+        let next = if forward {
+            starts.iter().find(|&&start| start > index)
+        } else {
+            starts.iter().rev().find(|&&start| start < index)
+        };
+        let (found, wrapped) = match next {
+            Some(&offset) => (Some(offset), false),
+            None if self.wrapscan && forward => (starts.first().cloned(), true),
+            None if self.wrapscan => (starts.last().cloned(), true),
+            None => (None, false),
+        };
+
+        let current = found.map_or(0, |offset| {
+            starts.iter().filter(|&&start| start <= offset).count()
+        });
+
+        self.found(
+            index,
+            found,
+            (wrapped, forward),
+            (current, starts.len()),
+            &format!(":grep {}", regex),
+            regex.as_str(),
+        );
+    }
+
+    // Moves to the match of a search from `index` and reports it, `current` of `total`
+    fn found(
+        &mut self,
+        index: usize,
+        found: Option<usize>,
+        (wrapped, forward): (bool, bool),
+        (current, total): (usize, usize),
+        search: &str,
+        pattern: &str,
+    ) {
+        match found {
+            Some(offset) => {
+                self.push_jump(index);
+                self.model.set_index(offset);
+                self.view.hex_view.scroll_to(self.model.get_index());
+                self.view.status_view.set_index(self.model.get_index());
+                self.view.status_view.set_body(&match (wrapped, forward) {
+                    (false, _) => format!("{} [{}/{}]", search, current, total),
+                    (true, true) => {
+                        format!(
+                            "search hit BOTTOM, continuing at TOP [{}/{}]",
+                            current, total
+                        )
+                    }
+                    (true, false) => {
+                        format!(
+                            "search hit TOP, continuing at BOTTOM [{}/{}]",
+                            current, total
+                        )
+                    }
+                });
+            }
+            None if self.wrapscan => {
+                self.view
+                    .status_view
+                    .set_body(&format!("pattern not found: {}", pattern));
+            }
+            None => {
+                let edge = if forward { "BOTTOM" } else { "TOP" };
+                self.view.status_view.set_body(&format!(
+                    "search hit {} without match for: {}",
+                    edge, pattern
+                ));
+            }
+        }
+    }
+
+    // Moves the caret to the next occurrence of the last `f` byte after it
+    pub fn find(&mut self) {
+        let byte = match self.find {
+            Some(byte) => byte,
+            None => {
+                self.view.status_view.set_body("no previous byte to find");
+                return;
+            }
+        };
+
+        let index = self.model.get_index();
+        self.model.make_contiguous();
+        match find_next(
+            &self.model.bytes(..),
+            &[Some(byte)],
+            index.saturating_add(1),
+            false,
+        ) {
+            Some(offset) => {
+                self.model.set_index(offset);
+                self.view.hex_view.scroll_to(self.model.get_index());
+                self.view.status_view.set_index(self.model.get_index());
+            }
+            None => {
+                self.view
+                    .status_view
+                    .set_body(&format!("byte not found: {:02x}", byte));
+            }
+        }
+    }
+
+    // Jumps are absolute and relative goto commands and searches, not ordinary motions
+    fn push_jump(&mut self, from: usize) {
+        self.jumps.truncate(self.jump);
+        if self.jumps.last() != Some(&from) {
+            self.jumps.push(from);
+        }
+        if self.jumps.len() > JUMPLIST_SIZE {
+            self.jumps.remove(0);
+        }
+        self.jump = self.jumps.len();
+    }
+
+    // Steps through the jump list, `Movement::Left` goes back to older positions
+    pub fn jump(&mut self, movement: Movement) {
+        let index = self.model.get_index();
+
+        match movement {
+            Movement::Left => {
+                // Remember where we started, so we can return there
+                if self.jump == self.jumps.len() {
+                    if self.jumps.last() != Some(&index) {
+                        self.jumps.push(index);
+                    }
+                    self.jump = self.jumps.len() - 1;
+                }
+
+                if self.jump == 0 {
+                    self.view.status_view.set_body("at start of jump list");
+                    return;
+                }
+                self.jump -= 1;
+            }
+            Movement::Right => {
+                if self.jump + 1 >= self.jumps.len() {
+                    self.view.status_view.set_body("at end of jump list");
+                    return;
+                }
+                self.jump += 1;
+            }
+        }
+
+        self.model.set_index(self.jumps[self.jump]);
+        self.view.hex_view.scroll_to(self.model.get_index());
+        self.view.status_view.set_index(self.model.get_index());
+    }
+
     // Update
 
     pub fn update(&mut self, msg: Msg) -> bool {
+        write_log(&mut self.log, format_args!("msg {:?}", msg));
+
         let mut run = true;
 
         match msg {
@@ -210,6 +1089,9 @@ impl Controller {
                 match self.model.caret {
                     Caret::Index(_) => {
                         self.insert(byte);
+                        if let Some((_, ref mut bytes)) = self.repeat {
+                            bytes.push(byte);
+                        }
                     }
                     Caret::Replace(_) => {
                         self.replace(byte);
@@ -217,24 +1099,76 @@ impl Controller {
                     _ => {}
                 }
 
-                self.model.snapshot();
+                self.snapshot();
+            }
+            Msg::Nibble(nibble, low) => {
+                // Overwrite one half of the byte under the caret
+                if let Some(byte) = self.model.byte_at(self.model.get_index()) {
+                    let nibble = nibble & 0x0f;
+                    if low {
+                        self.replace((byte & 0xf0) | nibble);
+                    } else {
+                        self.replace((byte & 0x0f) | (nibble << 4));
+                    }
+
+                    self.snapshot();
+                }
             }
             Msg::Move(dir) => {
+                let from = self.model.get_index();
+                let jumped = matches!(dir, Direction::Offset(_) | Direction::Relative(_));
+
                 match dir {
                     Direction::Left => self.model.dec_index(1),
                     Direction::Right => self.model.inc_index(1),
-                    Direction::Up => self.model.dec_index(16),
-                    Direction::Down => self.model.inc_index(16),
+                    // Rows are records, if set
+                    Direction::Up => self
+                        .model
+                        .dec_index(self.view.hex_view.get_record().unwrap_or(BYTES_PER_ROW)),
+                    Direction::Down => self
+                        .model
+                        .inc_index(self.view.hex_view.get_record().unwrap_or(BYTES_PER_ROW)),
                     Direction::Offset(offset) => {
+                        // Snap to the start of the enclosing row, if requested
+                        let offset = if self.snap {
+                            offset - (offset % BYTES_PER_ROW)
+                        } else {
+                            offset
+                        };
+
                         self.model.set_index(offset);
-                        self.view.hex_view.scroll_to(self.model.get_index());
-                        self.view.status_view.set_index(offset);
-                        self.view.status_view.set_body("");
+                        let index = self.model.get_index();
+                        self.view.hex_view.scroll_to(index);
+                        self.view.status_view.set_index(index);
+
+                        // The caret can't go past the end, say so instead of landing elsewhere silently
+                        if index < offset {
+                            self.view.status_view.set_body(&format!(
+                                "offset beyond end of file (clamped to {:#x})",
+                                index
+                            ));
+                        } else {
+                            self.view.status_view.set_body("");
+                        }
+                    }
+                    // Goes through the offset, which records the jump. 100% is the last byte.
+                    Direction::Percent(percent) => {
+                        let len = self.model.len() as u128;
+                        let offset = (len * min(percent, 100) as u128 / 100) as usize;
+                        let offset = min(offset, self.model.len().saturating_sub(1));
+                        self.update(Msg::Move(Direction::Offset(offset)));
+                    }
+                    Direction::Relative(distance) => {
+                        if distance < 0 {
+                            self.model.dec_index(distance.unsigned_abs());
+                        } else {
+                            self.model.inc_index(distance as usize);
+                        }
                     }
                     Direction::Newline => {
-                        self.model.inc_index(16);
+                        self.model.inc_index(BYTES_PER_ROW);
                         let index = self.model.get_index();
-                        self.model.set_index(index - (index % 16));
+                        self.model.set_index(index - (index % BYTES_PER_ROW));
                     }
                     Direction::Revert => {
                         if let Caret::Visual(ref mut start, ref mut end) = self.model.caret {
@@ -243,16 +1177,26 @@ impl Controller {
                     }
                 };
 
+                if jumped && self.model.get_index() != from {
+                    self.push_jump(from);
+                }
+
                 self.view.hex_view.scroll_to(self.model.get_index());
                 self.view.status_view.set_index(self.model.get_index());
             }
             Msg::Quit => {
-                if self.model.is_modified() {
+                if !self.model.is_modified() {
+                    run = false;
+                } else if self.model.path.is_empty() {
                     self.view
                         .status_view
-                        .set_body("save your changes with :w or force quit with :q!");
+                        .set_body("no file name (save with :saveas <file> or discard with :q!)");
                 } else {
-                    run = false;
+                    // The next key decides, see `VimState::Confirm`
+                    self.view
+                        .status_view
+                        .set_body("Save changes? (y)es (n)o (c)ancel");
+                    self.state = VimState::Confirm(PendingAction::Quit);
                 }
             }
             Msg::QuitWithoutSaving => {
@@ -261,9 +1205,60 @@ impl Controller {
             Msg::Save => {
                 self.save();
             }
-            Msg::SaveAs(path) => {
-                self.save_as(path);
+            Msg::SaveAs(path, force) => {
+                // Do not silently truncate another existing file
+                if !force && Path::new(&path).exists() && !same_file(&path, &self.model.path) {
+                    self.fail(&format!("\"{}\" exists (use :saveas! to overwrite)", path));
+                } else {
+                    self.save_as(path);
+                }
+            }
+            Msg::Export(path, width, force) => {
+                if !force && Path::new(&path).exists() {
+                    self.fail(&format!("\"{}\" exists (use :export! to overwrite)", path));
+                    return true;
+                }
+
+                // Defaults to the bytes per row of the view
+                let (start, end) = self.selection_or_all();
+                let dump = xxd_dump(
+                    &self.model.bytes(start..end),
+                    start,
+                    width.unwrap_or(BYTES_PER_ROW),
+                );
+
+                match write(&path, dump) {
+                    Ok(_) => self.view.status_view.set_body(&format!(
+                        "{} bytes exported to \"{}\"",
+                        end - start,
+                        path
+                    )),
+                    Err(error) => {
+                        self.fail(&format!("could not export to \"{}\": {}", path, error))
+                    }
+                }
+            }
+            Msg::Append(path) => {
+                let (start, end) = self.selection_or_all();
+
+                match self.model.append_to(&path, start..end) {
+                    Ok(_) => self.view.status_view.set_body(&format!(
+                        "{} bytes appended to \"{}\"",
+                        end - start,
+                        path
+                    )),
+                    Err(error) => {
+                        self.fail(&format!("could not append to \"{}\": {}", path, error))
+                    }
+                }
             }
+            Msg::EditExternal => match env::var("HEXEDITOR").or_else(|_| env::var("EDITOR")) {
+                Ok(ref editor) if !editor.trim().is_empty() => self.edit_external(editor),
+                _ => self
+                    .view
+                    .status_view
+                    .set_body("no external editor (set $HEXEDITOR or $EDITOR)"),
+            },
             Msg::SaveAndQuit => {
                 if self.save() {
                     run = false;
@@ -308,118 +1303,132 @@ impl Controller {
                 }
             },
             Msg::Delete(movement) => {
-                if self.model.buffer.is_empty() {
+                if self.model.is_empty() {
                     return true;
                 }
 
                 match movement {
                     Some(Movement::Left) => {
                         self.remove_left();
-                        self.model.snapshot();
+                        if let Some((_, ref mut bytes)) = self.repeat {
+                            bytes.pop();
+                        }
+                        self.snapshot();
                     }
                     Some(Movement::Right) => {
+                        // There is nothing at the end, e.g. with `virtualedit`
+                        if self.model.get_index() >= self.model.len() {
+                            return true;
+                        }
+
                         if let Caret::Offset(_) = self.model.caret {
                             self.yank = Some(
-                                self.model.buffer
-                                    [self.model.get_index()..self.model.get_index() + 1]
-                                    .to_owned(),
+                                self.model
+                                    .bytes(self.model.get_index()..self.model.get_index() + 1)
+                                    .into_owned(),
                             );
                         }
                         self.remove_right();
-                        self.model.snapshot();
+                        self.snapshot();
                     }
                     None => {
-                        if let Caret::Visual(start, end) = self.model.caret {
-                            let (start, end) = if usize::from(start) > usize::from(end) {
-                                (end, start)
-                            } else {
-                                (start, end)
-                            };
-
-                            self.yank = Some(
-                                self.model.buffer[start.into()..usize::from(end) + 1].to_owned(),
-                            );
+                        if let Some((start, end)) = self.selection() {
+                            self.yank = Some(self.model.bytes(start..=end).into_owned());
 
-                            if let Err(e) = self.model.edit(start.into(), usize::from(end) + 1, &[])
-                            {
+                            if let Err(e) = self.model.edit(start, end + 1, &[]) {
                                 self.view
                                     .status_view
                                     .set_body(&format!("could not remove range ({})", e));
                             } else {
-                                self.model.set_index(start.into());
+                                self.model.set_index(start);
                             }
 
                             self.view.hex_view.scroll_to(self.model.get_index());
 
-                            self.model.snapshot();
+                            self.snapshot();
                         }
                     }
                 }
             }
-            Msg::ToNormal => {
-                self.model.caret = match self.model.caret {
-                    Caret::Index(index) => Caret::Offset(UsizeMax::new(
-                        index.value.saturating_sub(1),
-                        index.get_maximum().saturating_sub(1),
-                    )),
-                    Caret::Offset(index) | Caret::Replace(index) | Caret::Visual(_, index) => {
-                        Caret::Offset(index)
-                    }
-                };
+            Msg::Operate(operator, motion, count) => {
+                if self.model.is_empty() {
+                    return true;
+                }
 
-                self.view.status_view.set_body(&format!(
-                    "{}-- Normal ({:?}) --{}",
-                    termion::style::Bold,
-                    self.mode,
-                    termion::style::Reset
-                )); // TODO
+                // A count of zero is taken as one, like in Vim
+                let (start, end) = self.motion_range(motion, max(count.unwrap_or(1), 1));
+                if start == end {
+                    return true;
+                }
+
+                self.yank = Some(self.model.bytes(start..end).into_owned());
+
+                match operator {
+                    Operator::Delete => {
+                        if let Err(e) = self.model.edit(start, end, &[]) {
+                            self.view
+                                .status_view
+                                .set_body(&format!("could not remove range ({})", e));
+                        } else {
+                            self.model.set_index(start);
+                        }
+
+                        self.view.hex_view.scroll_to(self.model.get_index());
+                        self.snapshot();
+                    }
+                    // Like in Vim, the caret moves to the start of the yanked range
+                    Operator::Yank => {
+                        self.model.set_index(start);
+                        self.view.hex_view.scroll_to(start);
+                    }
+                }
             }
-            Msg::ToInsert(_repeat) => {
-                self.model.caret = match self.model.caret {
-                    Caret::Index(index) => Caret::Index(index),
-                    Caret::Offset(index) | Caret::Replace(index) | Caret::Visual(_, index) => {
-                        Caret::Index(UsizeMax::new(
-                            index.value,
-                            index.get_maximum().saturating_add(1),
-                        ))
+            Msg::ToNormal => {
+                // Complete a counted insert, e.g. `3i ff <Esc>` inserts ff three times
+                if let Some((count, bytes)) = self.repeat.take() {
+                    if let Caret::Index(_) = self.model.caret {
+                        if count > 1 && !bytes.is_empty() {
+                            for _ in 1..count {
+                                let index = self.model.get_index();
+                                self.paste(index, &bytes);
+                            }
+                            self.snapshot();
+                        }
                     }
-                };
+                }
 
-                self.view.status_view.set_body(&format!(
-                    "{}-- Insert ({:?}) --{}",
-                    termion::style::Bold,
-                    self.mode,
-                    termion::style::Reset
-                )); // TODO
-            }
-            Msg::ToAppend(_repeat) => {
+                let last_offset = self.model.last_offset();
                 self.model.caret = match self.model.caret {
-                    Caret::Index(index) => Caret::Index(index),
+                    Caret::Index(index) => {
+                        Caret::Offset(UsizeMax::new(index.value().saturating_sub(1), last_offset))
+                    }
                     Caret::Offset(index) | Caret::Replace(index) | Caret::Visual(_, index) => {
-                        Caret::Index(UsizeMax::new(
-                            index.value,
-                            index.get_maximum().saturating_add(1),
-                        ))
+                        Caret::Offset(UsizeMax::new(index.value(), last_offset))
                     }
                 };
 
                 self.view.status_view.set_body(&format!(
-                    "{}-- Insert ({:?}) --{}",
+                    "{}-- Normal ({:?}) --{}",
                     termion::style::Bold,
                     self.mode,
                     termion::style::Reset
                 )); // TODO
-
+            }
+            Msg::ToInsert(repeat) => {
+                self.enter_insert(repeat);
+            }
+            Msg::ToAppend(repeat) => {
+                self.enter_insert(repeat);
                 self.update(Msg::Move(Direction::Right));
             }
             Msg::ToReplace => {
+                let last_offset = self.model.last_offset();
                 self.model.caret = match self.model.caret {
-                    Caret::Index(index) => Caret::Replace(UsizeMax::new(
-                        index.value,
-                        index.get_maximum().saturating_sub(1),
-                    )),
-                    Caret::Offset(index) | Caret::Replace(index) | Caret::Visual(_, index) => {
-                        Caret::Replace(index)
+                    Caret::Index(index)
+                    | Caret::Offset(index)
+                    | Caret::Replace(index)
+                    | Caret::Visual(_, index) => {
+                        Caret::Replace(UsizeMax::new(index.value(), last_offset))
                     }
                 };
 
@@ -433,10 +1442,15 @@ impl Controller {
             Msg::ToVisual => {
                 self.model.caret = match self.model.caret {
                     Caret::Index(index) => Caret::Visual(
-                        UsizeMax::new(index.value, index.get_maximum().saturating_sub(1)),
-                        UsizeMax::new(index.value, index.get_maximum().saturating_sub(1)),
+                        UsizeMax::new(index.value(), index.get_maximum().saturating_sub(1)),
+                        UsizeMax::new(index.value(), index.get_maximum().saturating_sub(1)),
                     ),
-                    Caret::Offset(index) | Caret::Replace(index) => Caret::Visual(index, index),
+                    // Only bytes are selected, not the end after them
+                    Caret::Offset(index) | Caret::Replace(index) => {
+                        let index =
+                            UsizeMax::new(index.value(), self.model.len().saturating_sub(1));
+                        Caret::Visual(index, index)
+                    }
                     Caret::Visual(start, begin) => Caret::Visual(start, begin),
                 };
 
@@ -449,57 +1463,252 @@ impl Controller {
             Msg::ToCommand => {
                 self.view.status_view.set_body(":");
             }
-            Msg::ClipboardCopy => {
-                if self.model.buffer.is_empty() {
+            Msg::ClipboardCopy(force) => {
+                if self.model.is_empty() {
                     return true;
                 }
+                self.detect_clipboard();
 
                 let bytes = match self.model.caret {
-                    Caret::Offset(index) => &self.model.buffer[index.value..index.value + 1],
-                    Caret::Visual(start, end) => {
-                        let (start, end) = if usize::from(start) > usize::from(end) {
-                            (end, start)
-                        } else {
-                            (start, end)
-                        };
-
-                        &self.model.buffer[start.into()..usize::from(end) + 1]
+                    // With virtualedit, the caret may rest past the last byte
+                    Caret::Offset(index) => match self.model.byte_at(index.value()) {
+                        Some(byte) => vec![byte].into(),
+                        None => return true,
+                    },
+                    Caret::Visual(..) => {
+                        // Safe-from-panic: The caret is visual, thus there is a selection
+                        let (start, end) = self.selection().unwrap();
+                        self.model.bytes(start..=end)
                     }
                     _ => return true,
                 };
 
-                match save_to_clipboard(bytes) {
+                if !force && bytes.len() > CLIPBOARD_LIMIT {
+                    self.view.status_view.set_body(&format!(
+                        "{} bytes are a lot to copy (use :copy! to copy anyway)",
+                        bytes.len()
+                    ));
+                    return true;
+                }
+
+                let msg = match self.clipboard {
+                    Clipboard::Detect | Clipboard::System => save_to_clipboard(&bytes),
+                    Clipboard::Osc52(_) => {
+                        self.clipboard = Clipboard::Osc52(bytes.to_vec());
+                        match self.view.send(&osc52_copy(&bytes)) {
+                            Ok(_) => describe_copy(&bytes, "the terminal's clipboard"),
+                            Err(e) => Err(format!("Failed copy to clipboard ({})", e)),
+                        }
+                    }
+                    Clipboard::Session(_) => {
+                        self.clipboard = Clipboard::Session(bytes.to_vec());
+                        describe_copy(&bytes, "the session clipboard")
+                            .map(|msg| format!("{}, no system clipboard found", msg))
+                    }
+                };
+                match msg {
                     Ok(msg) | Err(msg) => self.view.status_view.set_body(&msg),
                 };
             }
-            Msg::ClipboardPaste => match read_from_clipboard() {
-                Ok(value) => {
-                    let index = self.model.get_index();
-                    self.paste(index, &value);
-                    self.model.snapshot();
+            Msg::ClipboardPaste => {
+                self.detect_clipboard();
+                let value = match &self.clipboard {
+                    Clipboard::Detect | Clipboard::System => read_from_clipboard(),
+                    Clipboard::Osc52(bytes) | Clipboard::Session(bytes) if !bytes.is_empty() => {
+                        Ok(bytes.clone())
+                    }
+                    // Typing digits still works, e.g. pasting the hex with the terminal
+                    Clipboard::Osc52(_) => Err(
+                        "the terminal's clipboard can't be read, paste it in insert mode instead"
+                            .into(),
+                    ),
+                    Clipboard::Session(_) => {
+                        Err("the session clipboard is empty, no system clipboard found".into())
+                    }
+                };
+                match value {
+                    Ok(value) => {
+                        self.paste_over_selection(&value);
+                        self.snapshot();
+                    }
+                    Err(ref e) => {
+                        self.view.status_view.set_body(e);
+                    }
+                }
+            }
+            Msg::Filter(cmd) => {
+                let (start, end) = self.selection_or_all();
+
+                match filter_through(&cmd, &self.model.bytes(start..end)) {
+                    Ok(output) => {
+                        if let Err(e) = self.model.edit(start, end, &output) {
+                            self.view
+                                .status_view
+                                .set_body(&format!("could not replace range ({})", e));
+                            return true;
+                        }
+
+                        if let Caret::Visual(..) = self.model.caret {
+                            self.model.set_index(start);
+                            self.update(Msg::ToNormal);
+                        }
+
+                        self.view.hex_view.scroll_to(self.model.get_index());
+                        self.view.status_view.set_body(&format!(
+                            "{} bytes filtered through \"{}\" ({} bytes)",
+                            end - start,
+                            cmd,
+                            output.len()
+                        ));
+
+                        self.snapshot();
+                    }
+                    Err(ref e) => {
+                        self.view.status_view.set_body(e);
+                    }
                 }
-                Err(ref e) => {
-                    self.view.status_view.set_body(e);
+            }
+            Msg::Sort(descending) => {
+                let (start, end) = match self.selection() {
+                    Some(selection) => selection,
+                    None => {
+                        self.view.status_view.set_body("no selection to sort");
+                        return true;
+                    }
+                };
+
+                let mut bytes = self.model.bytes(start..=end).into_owned();
+                if descending {
+                    bytes.sort_unstable_by(|a, b| b.cmp(a));
+                } else {
+                    bytes.sort_unstable();
                 }
-            },
+
+                if let Err(e) = self.model.edit(start, end + 1, &bytes) {
+                    self.view
+                        .status_view
+                        .set_body(&format!("could not sort range ({})", e));
+                } else {
+                    self.view
+                        .status_view
+                        .set_body(&format!("{} bytes sorted", bytes.len()));
+                    self.snapshot();
+                }
+            }
+            Msg::Reverse => {
+                let (start, end) = self.selection_or_all();
+
+                let mut bytes = self.model.bytes(start..end).into_owned();
+                bytes.reverse();
+
+                if let Err(e) = self.model.edit(start, end, &bytes) {
+                    self.view
+                        .status_view
+                        .set_body(&format!("could not reverse range ({})", e));
+                } else {
+                    self.view
+                        .status_view
+                        .set_body(&format!("{} bytes reversed", bytes.len()));
+                    self.snapshot();
+                }
+            }
+            Msg::Case(upper) => {
+                let (start, end) = self.selection_or_all();
+
+                let mut bytes = self.model.bytes(start..end).into_owned();
+                if upper {
+                    bytes.make_ascii_uppercase();
+                } else {
+                    bytes.make_ascii_lowercase();
+                }
+
+                if let Err(e) = self.model.edit(start, end, &bytes) {
+                    self.view
+                        .status_view
+                        .set_body(&format!("could not change case ({})", e));
+                    return true;
+                }
+
+                if let Caret::Visual(..) = self.model.caret {
+                    self.model.set_index(start);
+                    self.update(Msg::ToNormal);
+                }
+
+                self.view.hex_view.scroll_to(self.model.get_index());
+                self.snapshot();
+            }
+            Msg::Timestamp(width) => {
+                let secs = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                let bytes = match (width, u32::try_from(secs)) {
+                    (4, Ok(secs)) if self.endian == Endian::Little => secs.to_le_bytes().to_vec(),
+                    (4, Ok(secs)) => secs.to_be_bytes().to_vec(),
+                    (8, _) if self.endian == Endian::Little => secs.to_le_bytes().to_vec(),
+                    (8, _) => secs.to_be_bytes().to_vec(),
+                    (4, Err(_)) => {
+                        self.view
+                            .status_view
+                            .set_body("the time doesn't fit into 4 bytes, use :timestamp 8");
+                        return true;
+                    }
+                    _ => {
+                        self.view
+                            .status_view
+                            .set_body("timestamps are 4 or 8 bytes wide");
+                        return true;
+                    }
+                };
+
+                // Like `P`, ending on the last inserted byte
+                let index = self.model.get_index();
+                self.paste(index, &bytes);
+                self.update(Msg::Move(Direction::Left));
+                self.snapshot();
+            }
+            Msg::Swap(width) => {
+                if width == 0 {
+                    self.view.status_view.set_body("group size must not be 0");
+                    return true;
+                }
+
+                let (start, end) = self.selection_or_all();
+
+                let mut bytes = self.model.bytes(start..end).into_owned();
+                let mut groups = bytes.chunks_exact_mut(width);
+                for group in &mut groups {
+                    group.reverse();
+                }
+                let trailing = groups.into_remainder().len();
+
+                if let Err(e) = self.model.edit(start, end, &bytes) {
+                    self.view
+                        .status_view
+                        .set_body(&format!("could not swap range ({})", e));
+                } else {
+                    let mut msg =
+                        format!("{} groups of {} bytes swapped", bytes.len() / width, width);
+                    if trailing > 0 {
+                        msg.push_str(&format!(", {} trailing bytes left untouched", trailing));
+                    }
+                    self.view.status_view.set_body(&msg);
+                    self.snapshot();
+                }
+            }
             Msg::Yank => {
-                if self.model.buffer.is_empty() {
+                if self.model.is_empty() {
                     return true;
                 }
 
                 match self.model.caret {
                     Caret::Offset(index) => {
-                        self.yank = Some(vec![self.model.buffer[index.value]]);
+                        self.yank = self.model.byte_at(index.value()).map(|byte| vec![byte]);
                     }
-                    Caret::Visual(start, end) => {
-                        let (start, end) = if usize::from(start) > usize::from(end) {
-                            (end, start)
-                        } else {
-                            (start, end)
-                        };
-
-                        self.yank =
-                            Some(self.model.buffer[start.into()..usize::from(end) + 1].to_owned());
+                    Caret::Visual(..) => {
+                        // Safe-from-panic: The caret is visual, thus there is a selection
+                        let (start, end) = self.selection().unwrap();
+                        self.yank = Some(self.model.bytes(start..=end).into_owned());
                         self.update(Msg::ToNormal);
                     }
                     _ => return true,
@@ -512,58 +1721,407 @@ impl Controller {
                             let index = self.model.get_index();
                             self.paste(index, &value);
                             self.update(Msg::Move(Direction::Left));
-                            self.model.snapshot();
+                            self.snapshot();
                         }
                         Some(Movement::Right) => {
-                            let index = self.model.get_index() + 1;
+                            let index = min(self.model.get_index() + 1, self.model.len());
                             self.paste(index, &value);
-                            self.model.snapshot();
+                            self.snapshot();
                         }
                     }
                 }
             }
+            Msg::Search(pattern) => {
+                if pattern.is_empty() {
+                    // Like Vim, an empty pattern repeats the last search.
+                    self.search(true);
+                } else {
+                    self.search = Some(pattern);
+                    self.grep = None;
+                    self.search(true);
+                }
+            }
+            Msg::List(pattern) => {
+                self.model.make_contiguous();
+                let buffer = &*self.model.bytes(..);
+                let (_, total) = count_matches(buffer, &pattern, 0, self.ignorecase);
+                let offsets: Vec<_> =
+                    successors(find_next(buffer, &pattern, 0, self.ignorecase), |&offset| {
+                        find_next(buffer, &pattern, offset + 1, self.ignorecase)
+                    })
+                    .take(LIST_MAX)
+                    .map(|offset| format!("0x{:x}", offset))
+                    .collect();
+
+                let msg = match total {
+                    0 => format!("pattern not found: {}", format_hex_pattern(&pattern)),
+                    1 => format!("1 match: {}", offsets[0]),
+                    _ if total > LIST_MAX => format!(
+                        "{} matches: {}, and {} more",
+                        total,
+                        offsets.join(", "),
+                        total - LIST_MAX
+                    ),
+                    _ => format!("{} matches: {}", total, offsets.join(", ")),
+                };
+                self.view.status_view.set_body(&msg);
+            }
+            // Not Unicode aware, so `.` matches any byte but a line feed, as in `(?-u)`
+            Msg::Grep(pattern) => match RegexBuilder::new(&pattern)
+                .unicode(false)
+                .case_insensitive(self.ignorecase)
+                .build()
+            {
+                Ok(regex) => {
+                    self.grep = Some(regex);
+                    self.search(true);
+                }
+                Err(e) => {
+                    // The error points at the pattern over several lines, the last one says why
+                    let e = e.to_string();
+                    self.view.status_view.set_body(&format!(
+                        "invalid regex ({})",
+                        e.lines()
+                            .last()
+                            .unwrap_or_default()
+                            .trim_start_matches("error: ")
+                    ));
+                }
+            },
+            Msg::SearchNext(movement) => match movement {
+                Movement::Right => self.search(true),
+                Movement::Left => self.search(false),
+            },
+            Msg::Find(byte) => {
+                self.find = Some(byte);
+                self.find();
+            }
+            Msg::FindNext => self.find(),
+            Msg::Jump(movement) => self.jump(movement),
+            Msg::Undo | Msg::Redo if !self.model.undo_enabled() => {
+                self.view
+                    .status_view
+                    .set_body("undo is disabled (enable with :set undo on)");
+            }
             Msg::Undo => {
-                if !self.model.undo() {
-                    self.view.status_view.set_body("Nothing to undo");
+                match self.model.undo() {
+                    Some(scroll_start) => self.view.hex_view.set_scroll_start(scroll_start),
+                    None => self.view.status_view.set_body("Nothing to undo"),
                 }
                 self.view.hex_view.scroll_to(self.model.get_index());
             }
             Msg::Redo => {
-                if !self.model.redo() {
-                    self.view.status_view.set_body("Nothing to redo");
+                match self.model.redo() {
+                    Some(scroll_start) => self.view.hex_view.set_scroll_start(scroll_start),
+                    None => self.view.status_view.set_body("Nothing to redo"),
                 }
                 self.view.hex_view.scroll_to(self.model.get_index());
             }
+            Msg::SetMany(settings) => {
+                let mut first_error = None;
+                for setting in settings {
+                    match setting {
+                        Ok(setting) => {
+                            self.update(Msg::Set(setting));
+                        }
+                        Err(error) => {
+                            first_error = first_error.or(Some(error));
+                        }
+                    }
+                }
+
+                if let Some(error) = first_error {
+                    self.view.status_view.set_body(error);
+                }
+            }
+            Msg::Set(setting) => match setting {
+                Setting::Snap(snap) => {
+                    self.snap = snap;
+                }
+                Setting::Caret(style) => {
+                    self.view.hex_view.set_caret_style(style);
+                }
+                Setting::Wrapscan(wrapscan) => {
+                    self.wrapscan = wrapscan;
+                }
+                Setting::IncSearch(incsearch) => {
+                    self.incsearch = incsearch;
+                }
+                Setting::IgnoreCase(ignorecase) => {
+                    self.ignorecase = ignorecase;
+                }
+                Setting::ShowCmd(showcmd) => {
+                    self.showcmd = showcmd;
+                }
+                Setting::Nibble(nibble) => {
+                    self.nibble = nibble;
+                }
+                Setting::NumberWidth(width) => {
+                    self.view.hex_view.set_number_width(width);
+                }
+                Setting::ErrorBell(errorbell) => {
+                    self.errorbell = errorbell;
+                }
+                Setting::Tildes(tildes) => {
+                    self.view.set_tildes(tildes);
+                }
+                Setting::BackupCopy(backupcopy) => {
+                    self.model.backupcopy = backupcopy;
+                }
+                Setting::Mouse(mouse) => {
+                    self.mouse = mouse;
+                }
+                Setting::AutoWrite(autowrite) => {
+                    self.autowrite = autowrite;
+                }
+                Setting::RelativeNumber(relative) => {
+                    self.view.hex_view.set_relativenumber(relative);
+                }
+                Setting::Number(number) => {
+                    self.view.hex_view.set_number(number);
+                }
+                Setting::Clipboard(ClipboardKind::System) => {
+                    self.clipboard = Self::system_clipboard();
+                    if let Clipboard::Session(_) = self.clipboard {
+                        self.view
+                            .status_view
+                            .set_body("no system clipboard found, copies are kept for the session");
+                    }
+                }
+                Setting::Clipboard(ClipboardKind::Osc52) => {
+                    self.clipboard = Clipboard::Osc52(vec![]);
+                }
+                Setting::ColorColumn(column) => {
+                    self.view.hex_view.set_colorcolumn(column);
+                }
+                Setting::Encoding(encoding) => {
+                    self.view.hex_view.set_encoding(encoding);
+                }
+                Setting::Wrap(wrap) => {
+                    self.view.hex_view.set_wrap(wrap);
+                }
+                Setting::MatchByte(matchbyte) => {
+                    self.view.hex_view.set_matchbyte(matchbyte);
+                }
+                Setting::VirtualEdit(virtualedit) => {
+                    self.model.virtualedit = virtualedit;
+                    let last_offset = self.model.last_offset();
+                    if let Caret::Offset(ref mut index) | Caret::Replace(ref mut index) =
+                        self.model.caret
+                    {
+                        index.set_maximum(last_offset);
+                    }
+                }
+                Setting::Endian(endian) => {
+                    self.endian = endian;
+                }
+                Setting::Signed(signed) => {
+                    self.signed = signed;
+                }
+                Setting::CursorLine(cursorline) => {
+                    self.view.hex_view.set_cursorline(cursorline);
+                }
+                Setting::HexCase(upper) => {
+                    self.view.hex_view.set_uppercase(upper);
+                }
+                Setting::SwapFile(swapfile) => {
+                    self.model.swapfile = swapfile;
+                    if !swapfile {
+                        self.model.unlock();
+                    } else if let Err(msg) = self.model.lock() {
+                        self.view.status_view.set_body(&msg);
+                    }
+                }
+                Setting::FileFormat(fileformat) => {
+                    self.fileformat = fileformat;
+                }
+                Setting::LastStatus(laststatus) => {
+                    self.view.set_laststatus(laststatus);
+                    self.view.hex_view.scroll_to(self.model.get_index());
+                }
+                Setting::Entropy(entropy) => {
+                    self.view.hex_view.set_entropy(entropy);
+                }
+                Setting::Record(record) => {
+                    self.view
+                        .hex_view
+                        .set_record(if record == 0 { None } else { Some(record) });
+                }
+                Setting::Undo(undo) => {
+                    let scroll_start = self.view.hex_view.get_scroll_start();
+                    self.model.set_undo(undo, scroll_start);
+                }
+                Setting::UndoLevels(levels) => {
+                    self.model.set_undolevels(levels);
+                }
+                Setting::ScrollOff(rows) => {
+                    self.view.hex_view.set_scrolloff(rows);
+                    self.view.hex_view.scroll_to(self.model.get_index());
+                }
+            },
+            Msg::Map(key, cmd) => {
+                self.keymap.insert(key, cmd);
+            }
+            Msg::Note(note) => {
+                let index = self.model.get_index();
+                if self.model.byte_at(index).is_none() {
+                    self.view.status_view.set_body("no byte to note");
+                    return true;
+                }
+                match note {
+                    Some(note) => {
+                        self.model.notes.insert(index, note);
+                    }
+                    None => {
+                        self.model.notes.remove(&index);
+                    }
+                }
+            }
+            Msg::Info => {
+                let info = self.info();
+                self.view.status_view.set_body(&info);
+            }
             Msg::Show(msg) => {
                 self.view.status_view.set_body(&msg);
             }
             Msg::Redraw => {
+                let byte = match self.model.caret {
+                    Caret::Offset(index) => self.model.byte_at(index.value()),
+                    _ => None,
+                };
+                self.view.status_view.set_byte(byte);
+                self.view.status_view.note = self.model.notes.get(&self.model.get_index()).cloned();
+                self.view.status_view.pending = Some(self.pending.clone()).filter(|_| self.showcmd);
+                self.view.status_view.signed = self.signed;
+
                 if let Err(error) = self.view.draw(&self.model) {
                     // What to do when drawing failed?
                     // Try to report this on stderr and ignore further failures.
                     eprintln!("{}", error);
                 }
             }
+            Msg::Repaint => {
+                if let Err(error) = self.view.clear() {
+                    eprintln!("{}", error);
+                }
+                self.update(Msg::Redraw);
+            }
             Msg::Resize(size) => {
                 self.view.set_area(DrawArea {
                     origin: (1, 1),
                     dimens: size,
                 });
             }
-            Msg::Open(path) => {
-                self.open(&path);
-            }
-        };
+            Msg::Click(x, y) => {
+                let target = self.view.hex_view.cell_to_offset(x, y, self.model.len());
+
+                if let (true, Some(offset), VimState::Normal | VimState::Visual) =
+                    (self.mouse, target, &self.state)
+                {
+                    self.update(Msg::Move(Direction::Offset(offset)));
+                }
+            }
+            Msg::SelectWord => {
+                if let (Caret::Visual(start, end), false) =
+                    (self.model.caret.clone(), self.model.is_empty())
+                {
+                    let last = self.model.len() - 1;
+                    let (word_start, word_end) = self.word_around(min(end.value(), last));
+
+                    // The selection grows in the direction the caret moves
+                    let (start, end) = if end.value() >= start.value() {
+                        (min(start.value(), word_start), word_end)
+                    } else {
+                        (max(start.value(), word_end), word_start)
+                    };
+                    self.model.caret =
+                        Caret::Visual(UsizeMax::new(start, last), UsizeMax::new(end, last));
+                    self.view.hex_view.scroll_to(end);
+                    self.view.status_view.set_index(end);
+                }
+            }
+            Msg::Help(shown) => {
+                self.view.show_help(shown);
+                if shown {
+                    self.view
+                        .status_view
+                        .set_body("-- Help -- j/k and Ctrl-d/Ctrl-u to scroll, q to close");
+                    self.state = VimState::Help;
+                } else {
+                    self.view.status_view.set_body("");
+                }
+            }
+            Msg::ScrollHelp(lines) => {
+                self.view.scroll_help(lines);
+            }
+            Msg::SelectRange(anchor, end) => {
+                if self.model.is_empty() {
+                    self.view.status_view.set_body("nothing to select");
+                    return true;
+                }
+
+                let last = self.model.len() - 1;
+                self.update(Msg::ToNormal);
+                self.model.caret =
+                    Caret::Visual(UsizeMax::new(anchor, last), UsizeMax::new(end, last));
+                self.view.hex_view.scroll_to(min(min(anchor, end), last));
+                self.view.status_view.set_index(min(end, last));
+                self.update(Msg::ToVisual);
+            }
+            Msg::Open(path) => {
+                if !self.model.is_modified() || (self.autowrite && self.save()) {
+                    self.open(&path);
+                } else if !self.autowrite {
+                    self.view
+                        .status_view
+                        .set_body("unsaved changes (save with :w first or :set autowrite on)");
+                }
+            }
+        };
 
         run
     }
 
     // Transitions
 
+    // Runs a `:` command, returns whether to keep running
+    fn execute(&mut self, cmd: &str) -> bool {
+        match Msg::parse(cmd) {
+            Ok(msg) => self.update(msg),
+            Err(msg) => self.update(Msg::Show(msg.to_string())),
+        }
+    }
+
+    // Signals a key that has no meaning in the current state, if enabled
+    fn reject(&mut self) {
+        if self.errorbell {
+            if let Err(error) = self.view.bell() {
+                eprintln!("{}", error);
+            }
+            self.view.status_view.set_body("invalid input");
+        }
+    }
+
+    // Shows why a command failed to write, and keeps it for `source` to report
+    fn fail(&mut self, msg: &str) {
+        self.view.status_view.set_body(msg);
+        self.failure = Some(msg.into());
+    }
+
+    // State to return to after a command, commands may keep the visual selection or ask to confirm
+    fn resume(&self) -> VimState {
+        match (&self.state, &self.model.caret) {
+            (VimState::Confirm(action), _) => VimState::Confirm(*action),
+            (VimState::Help, _) => VimState::Help,
+            (_, Caret::Visual(..)) => VimState::Visual,
+            _ => VimState::Normal,
+        }
+    }
+
     // TODO: Refactor into VimStateMachine
     pub fn transition(&mut self, key: Key) -> bool {
         use termion::event::Key::{
-            Alt, Backspace, Char, Ctrl, Delete, Down, Esc, Insert, Left, Right, Up,
+            Alt, Backspace, Char, Ctrl, Delete, Down, End, Esc, Home, Insert, Left, Right, Up,
         };
 
         // TODO: Quickfix for tmux
@@ -571,8 +2129,33 @@ impl Controller {
 
         let mut run = true;
 
+        // A pending count applies to the next key only
+        let count = self.count.take();
+
+        write_log(
+            &mut self.log,
+            format_args!("key {:?} in {:?}", key, self.state),
+        );
+
+        // Esc drops whatever was typed so far in any state and returns to Normal mode
+        if key == Esc {
+            self.pending.clear();
+            self.leave_search();
+            self.view.show_help(false);
+            self.update(Msg::ToNormal);
+            self.state = VimState::Normal;
+            write_log(&mut self.log, format_args!("state {:?}", self.state));
+            return true;
+        }
+
         self.state = match self.state.clone() {
             VimState::Normal => match key {
+                // User-defined mappings take precedence over the built-in bindings
+                key if self.keymap.contains_key(&key) => {
+                    let cmd = self.keymap[&key].clone();
+                    run = self.execute(&cmd);
+                    self.resume()
+                }
                 Left | Right | Up | Down | Char('h') | Char('l') | Char('k') | Char('j') => {
                     self.update(Msg::Move(Direction::try_from(key).unwrap()));
                     VimState::Normal
@@ -585,25 +2168,75 @@ impl Controller {
                     self.update(Msg::Switch(None));
                     VimState::Normal
                 }
+                Char(c) if c.is_ascii_digit() && (c != '0' || count.is_some()) => {
+                    // Safe-from-panic: c is an ascii digit
+                    let digit = c.to_digit(10).unwrap() as usize;
+                    self.count = Some(count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                    VimState::Normal
+                }
                 Char('a') => {
-                    self.update(Msg::ToAppend(None));
+                    self.update(Msg::ToAppend(count));
                     VimState::Insert(InputStateMachine::new(self.mode))
                 }
                 Char('i') => {
-                    self.update(Msg::ToInsert(None));
+                    self.update(Msg::ToInsert(count));
+                    VimState::Insert(InputStateMachine::new(self.mode))
+                }
+                // Relative, as snapping would pull an offset back to the start of the last row
+                Char('A') => {
+                    self.update(Msg::ToInsert(count));
+                    let distance = self.model.len() - self.model.get_index();
+                    self.update(Msg::Move(Direction::Relative(distance as isize)));
                     VimState::Insert(InputStateMachine::new(self.mode))
                 }
+                // Unlike Vim's lines, a count is the byte offset to go to
+                Char('G') => {
+                    let last = self.model.len().saturating_sub(1);
+                    let offset = count.unwrap_or(last);
+                    self.update(Msg::Move(Direction::Offset(offset)));
+
+                    // Jumps past the end are reported already
+                    if count.is_some() && self.view.status_view.body.is_empty() {
+                        self.view.status_view.set_body(&format!(
+                            "byte {} (0x{:x}), counts before G are byte offsets",
+                            offset, offset
+                        ));
+                    }
+                    VimState::Normal
+                }
+                Char('g') => {
+                    // The count applies to the key after the prefix
+                    self.count = count;
+                    self.update(Msg::Show("g".into()));
+                    VimState::Goto
+                }
                 Delete | Char('x') => {
                     self.update(Msg::Delete(Some(Movement::Right)));
                     VimState::Normal
                 }
+                Char('d') => {
+                    // The count applies to the motion after the operator
+                    self.count = count;
+                    self.update(Msg::Show("d".into()));
+                    VimState::Operator(Operator::Delete)
+                }
                 Char('r') => {
                     self.update(Msg::ToReplace);
                     VimState::Replace(InputStateMachine::new(self.mode), false)
                 }
                 Char('R') => {
                     self.update(Msg::ToReplace);
-                    VimState::Replace(InputStateMachine::new(self.mode), true)
+                    match self.mode {
+                        InputMode::Hex if self.nibble => {
+                            self.update(Msg::Show(format!(
+                                "{}-- Replace (Nibble) --{}",
+                                termion::style::Bold,
+                                termion::style::Reset
+                            )));
+                            VimState::Nibble(false)
+                        }
+                        _ => VimState::Replace(InputStateMachine::new(self.mode), true),
+                    }
                 }
                 Char('v') => {
                     self.update(Msg::ToVisual);
@@ -613,17 +2246,48 @@ impl Controller {
                     self.update(Msg::ToCommand);
                     VimState::Command(String::new())
                 }
+                Char('/') => {
+                    self.search_start = Some(self.model.get_index());
+                    self.update(Msg::Show("/".into()));
+                    VimState::Search(String::new())
+                }
+                Char('n') => {
+                    self.update(Msg::SearchNext(Movement::Right));
+                    VimState::Normal
+                }
+                Char('N') => {
+                    self.update(Msg::SearchNext(Movement::Left));
+                    VimState::Normal
+                }
+                Char('f') => {
+                    self.update(Msg::Show("f".into()));
+                    VimState::Find(InputStateMachine::new(InputMode::Hex))
+                }
+                Char(';') => {
+                    self.update(Msg::FindNext);
+                    VimState::Normal
+                }
+                Ctrl('o') => {
+                    self.update(Msg::Jump(Movement::Left));
+                    VimState::Normal
+                }
+                // Most terminals send Ctrl-i as Tab, which switches the input mode instead
+                Ctrl('i') => {
+                    self.update(Msg::Jump(Movement::Right));
+                    VimState::Normal
+                }
                 Char('\n') => {
                     self.update(Msg::Move(Direction::Newline));
                     VimState::Normal
                 }
                 Ctrl('c') => {
-                    self.update(Msg::ClipboardCopy);
+                    self.update(Msg::ClipboardCopy(false));
                     VimState::Normal
                 }
                 Char('y') => {
-                    self.update(Msg::Yank);
-                    VimState::Normal
+                    self.count = count;
+                    self.update(Msg::Show("y".into()));
+                    VimState::Operator(Operator::Yank)
                 }
                 Char('p') => {
                     self.update(Msg::Paste(Some(Movement::Right)));
@@ -641,12 +2305,43 @@ impl Controller {
                     self.update(Msg::Redo);
                     VimState::Normal
                 }
-                Esc => {
-                    self.update(Msg::ToNormal);
+                Ctrl('l') => {
+                    self.update(Msg::Repaint);
+                    VimState::Normal
+                }
+                Char('?') => {
+                    self.update(Msg::Help(true));
+                    VimState::Help
+                }
+                _ => {
+                    self.reject();
                     VimState::Normal
                 }
-                _ => VimState::Normal,
             },
+            VimState::Help => {
+                let half_page = max(self.view.help_height() / 2, 1) as isize;
+                let lines = match key {
+                    Down | Char('j') | Char('\n') => Some(1),
+                    Up | Char('k') => Some(-1),
+                    Ctrl('d') | Char(' ') => Some(half_page),
+                    Ctrl('u') => Some(-half_page),
+                    _ => None,
+                };
+                match (key, lines) {
+                    (_, Some(lines)) => {
+                        self.update(Msg::ScrollHelp(lines));
+                        VimState::Help
+                    }
+                    (Char('q'), _) => {
+                        self.update(Msg::Help(false));
+                        VimState::Normal
+                    }
+                    _ => {
+                        self.reject();
+                        VimState::Help
+                    }
+                }
+            }
             VimState::Insert(mut machine) => {
                 if machine.initial() {
                     match key {
@@ -680,15 +2375,30 @@ impl Controller {
                             self.update(Msg::Switch(None));
                             VimState::Insert(InputStateMachine::new(self.mode))
                         }
+                        // Enter inserts a line ending, it can't be typed as ASCII otherwise
+                        Char('\n') if matches!(self.mode, InputMode::Ascii) => {
+                            for &byte in self.fileformat.line_ending() {
+                                self.update(Msg::Byte(byte));
+                            }
+                            VimState::Insert(machine)
+                        }
                         Ctrl('v') => {
                             self.update(Msg::ClipboardPaste);
                             VimState::Insert(machine)
                         }
-                        Esc => {
-                            self.update(Msg::ToNormal);
-                            VimState::Normal
+                        // Like Vim's Ctrl-Q, as Ctrl-V already pastes
+                        Ctrl('q') => {
+                            self.view.status_view.set_body(&format!(
+                                "{}-- Insert (Literal) --{}",
+                                termion::style::Bold,
+                                termion::style::Reset
+                            ));
+                            VimState::Literal(InputStateMachine::new(InputMode::Hex))
+                        }
+                        _ => {
+                            self.reject();
+                            VimState::Insert(machine)
                         }
-                        _ => VimState::Insert(machine),
                     }
                 } else {
                     match key {
@@ -702,11 +2412,10 @@ impl Controller {
                                 InputState::Incomplete(_) => VimState::Insert(machine),
                             }
                         }
-                        Esc => {
-                            self.update(Msg::ToNormal);
-                            VimState::Normal
+                        _ => {
+                            self.reject();
+                            VimState::Insert(machine)
                         }
-                        _ => VimState::Insert(machine),
                     }
                 }
             }
@@ -744,11 +2453,10 @@ impl Controller {
                             self.update(Msg::Switch(None));
                             VimState::Replace(InputStateMachine::new(self.mode), many)
                         }
-                        Esc => {
-                            self.update(Msg::ToNormal);
-                            VimState::Normal
+                        _ => {
+                            self.reject();
+                            VimState::Replace(machine, many)
                         }
-                        _ => VimState::Replace(machine, many),
                     }
                 } else {
                     match key {
@@ -768,51 +2476,204 @@ impl Controller {
                                 InputState::Incomplete(_) => VimState::Replace(machine, many),
                             }
                         }
-                        Esc => {
-                            self.update(Msg::ToNormal);
+                        _ => {
+                            self.reject();
+                            VimState::Replace(machine, many)
+                        }
+                    }
+                }
+            }
+            VimState::Nibble(low) => match key {
+                Left | Right | Up | Down | Char('h') | Char('l') | Char('k') | Char('j') => {
+                    self.update(Msg::Move(Direction::try_from(key).unwrap()));
+                    VimState::Nibble(false)
+                }
+                Backspace => {
+                    self.update(Msg::Move(Direction::Left));
+                    VimState::Nibble(false)
+                }
+                Char(c) if c.is_ascii_hexdigit() => {
+                    // Safe-from-panic: hex digits always convert
+                    self.update(Msg::Nibble(c.to_digit(16).unwrap() as u8, low));
+
+                    // Advance once both halves of the byte are set
+                    if low {
+                        self.update(Msg::Move(Direction::Right));
+                        VimState::Nibble(false)
+                    } else {
+                        VimState::Nibble(true)
+                    }
+                }
+                _ => {
+                    self.reject();
+                    VimState::Nibble(low)
+                }
+            },
+            VimState::Literal(mut machine) => match key {
+                Char(a) if machine.valid_input(a) => {
+                    machine.transition(key);
+                    match machine.state {
+                        InputState::Done(byte) => {
+                            self.update(Msg::Byte(byte));
+                            self.view.status_view.set_body(&format!(
+                                "{}-- Insert ({:?}) --{}",
+                                termion::style::Bold,
+                                self.mode,
+                                termion::style::Reset
+                            ));
+                            VimState::Insert(InputStateMachine::new(self.mode))
+                        }
+                        InputState::Incomplete(_) => VimState::Literal(machine),
+                    }
+                }
+                Backspace if !machine.initial() => {
+                    machine.transition(key);
+                    VimState::Literal(machine)
+                }
+                _ => {
+                    self.reject();
+                    VimState::Literal(machine)
+                }
+            },
+            VimState::Find(mut machine) => match key {
+                Char(a) if machine.valid_input(a) => {
+                    machine.transition(key);
+                    match machine.state {
+                        InputState::Done(byte) => {
+                            self.update(Msg::Show("".into()));
+                            self.update(Msg::Find(byte));
                             VimState::Normal
                         }
-                        _ => VimState::Replace(machine, many),
+                        InputState::Incomplete(ref digits) => {
+                            self.update(Msg::Show(format!("f{}", digits)));
+                            VimState::Find(machine)
+                        }
                     }
                 }
+                Backspace => {
+                    machine.transition(key);
+                    self.update(Msg::Show("f".into()));
+                    VimState::Find(machine)
+                }
+                _ => {
+                    self.reject();
+                    VimState::Find(machine)
+                }
+            },
+            VimState::TextObject => match key {
+                Char('w') => {
+                    self.update(Msg::SelectWord);
+                    VimState::Visual
+                }
+                _ => {
+                    self.reject();
+                    VimState::Visual
+                }
+            },
+            VimState::Goto => match key {
+                Char('I') => {
+                    self.update(Msg::Move(Direction::Offset(0)));
+                    self.update(Msg::ToInsert(count));
+                    VimState::Insert(InputStateMachine::new(self.mode))
+                }
+                _ => {
+                    self.reject();
+                    VimState::Normal
+                }
+            },
+            VimState::Confirm(action) => match (key, action) {
+                (Char('y'), PendingAction::Quit) => {
+                    run = self.update(Msg::SaveAndQuit);
+                    VimState::Normal
+                }
+                (Char('n'), PendingAction::Quit) => {
+                    run = self.update(Msg::QuitWithoutSaving);
+                    VimState::Normal
+                }
+                (Char('c'), _) => {
+                    self.update(Msg::Show("".into()));
+                    VimState::Normal
+                }
+                _ => {
+                    self.reject();
+                    VimState::Confirm(action)
+                }
+            },
+            VimState::Operator(operator) => {
+                let motion = match (operator, key) {
+                    (_, Char('l')) | (_, Right) => Some(Motion::Byte),
+                    (_, Char('w')) => Some(Motion::WordStart),
+                    (_, Char('e')) => Some(Motion::WordEnd),
+                    (_, Char('0')) | (_, Home) => Some(Motion::RowStart),
+                    (_, Char('$')) | (_, End) => Some(Motion::RowEnd),
+                    (_, Char('G')) => Some(Motion::BufferEnd),
+                    (Operator::Delete, Char('d')) | (Operator::Yank, Char('y')) => {
+                        Some(Motion::Row)
+                    }
+                    _ => None,
+                };
+
+                self.update(Msg::Show("".into()));
+                match motion {
+                    Some(motion) => self.update(Msg::Operate(operator, motion, count)),
+                    None => {
+                        self.reject();
+                        true
+                    }
+                };
+                VimState::Normal
             }
             VimState::Visual => match key {
                 Left | Right | Up | Down | Char('h') | Char('l') | Char('k') | Char('j') => {
                     self.update(Msg::Move(Direction::try_from(key).unwrap()));
                     VimState::Visual
                 }
+                Char(':') => {
+                    self.update(Msg::ToCommand);
+                    VimState::Command(String::new())
+                }
                 Char('y') => {
                     self.update(Msg::Yank);
                     VimState::Normal
                 }
                 Ctrl('c') => {
-                    self.update(Msg::ClipboardCopy);
+                    self.update(Msg::ClipboardCopy(false));
                     VimState::Visual
                 }
+                Ctrl('v') => {
+                    self.update(Msg::ClipboardPaste);
+                    self.resume()
+                }
                 Char('o') => {
                     self.update(Msg::Move(Direction::Revert));
                     VimState::Visual
                 }
+                Char('i') => {
+                    self.update(Msg::Show("i".into()));
+                    VimState::TextObject
+                }
                 Char('x') | Char('d') => {
                     self.update(Msg::Delete(None));
                     self.update(Msg::ToNormal);
                     VimState::Normal
                 }
-                Esc => {
-                    self.update(Msg::ToNormal);
+                Char('u') => {
+                    self.update(Msg::Case(false));
+                    VimState::Normal
+                }
+                Char('U') => {
+                    self.update(Msg::Case(true));
                     VimState::Normal
                 }
-                _ => VimState::Visual,
+                _ => {
+                    self.reject();
+                    VimState::Visual
+                }
             },
             VimState::Command(mut cmd) => match key {
                 Char('\n') => {
-                    match Msg::parse(&cmd) {
-                        Ok(cmd) => run = self.update(cmd),
-                        Err(msg) => {
-                            self.update(Msg::Show(msg.to_string()));
-                        }
-                    }
-                    VimState::Normal
+                    run = self.execute(&cmd);
+                    self.resume()
                 }
                 Backspace => {
                     cmd.pop();
@@ -824,14 +2685,61 @@ impl Controller {
                     self.update(Msg::Show(format!(":{}", &cmd)));
                     VimState::Command(cmd)
                 }
-                Esc => {
-                    self.update(Msg::Show("".into()));
+                _ => VimState::Command(cmd),
+            },
+            VimState::Search(mut pattern) => match key {
+                Char('\n') => {
+                    self.leave_search();
+                    match parse_hex_pattern(&pattern) {
+                        Ok(pattern) => {
+                            self.update(Msg::Search(pattern));
+                        }
+                        Err(msg) => {
+                            self.update(Msg::Show(msg.to_string()));
+                        }
+                    }
                     VimState::Normal
                 }
-                _ => VimState::Command(cmd),
+                Backspace => {
+                    pattern.pop();
+                    self.preview_search(&pattern);
+                    self.update(Msg::Show(format!("/{}", &pattern)));
+                    VimState::Search(pattern)
+                }
+                Char(c) => {
+                    pattern.push(c);
+                    self.preview_search(&pattern);
+                    self.update(Msg::Show(format!("/{}", &pattern)));
+                    VimState::Search(pattern)
+                }
+                _ => VimState::Search(pattern),
             },
         };
 
+        // Keys are collected until the command is complete
+        let complete = self.count.is_none()
+            && !matches!(
+                self.state,
+                VimState::Operator(_)
+                    | VimState::Goto
+                    | VimState::TextObject
+                    | VimState::Find(_)
+                    | VimState::Replace(_, false)
+            );
+        if complete {
+            self.pending.clear();
+        } else {
+            match key {
+                Char(c) => self.pending.push(c),
+                Ctrl(c) => self
+                    .pending
+                    .push_str(&format!("^{}", c.to_ascii_uppercase())),
+                _ => {}
+            }
+        }
+
+        write_log(&mut self.log, format_args!("state {:?}", self.state));
+
         run
     }
 }
@@ -846,6 +2754,7 @@ mod tests {
     use termion::{raw::IntoRawMode, screen::AlternateScreen};
 
     use super::*;
+    use crate::TempFile;
 
     impl Arbitrary for Msg {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
@@ -855,13 +2764,22 @@ mod tests {
                 //Quit, // FIXME
                 QuitWithoutSaving,
                 //Save,
-                //SaveAs(String::arbitrary(g)),
+                //SaveAs(String::arbitrary(g), bool::arbitrary(g)),
                 //SaveAndQuit,
+                //Export(String::arbitrary(g), Option::<usize>::arbitrary(g), bool::arbitrary(g)),
+                //Append(String::arbitrary(g)),
+                //EditExternal,
                 // ---
                 Byte(u8::arbitrary(g)),
+                Nibble(u8::arbitrary(g), bool::arbitrary(g)),
                 Move(Direction::arbitrary(g)),
                 Switch(Option::<InputMode>::arbitrary(g)),
                 Delete(Option::<Movement>::arbitrary(g)),
+                Operate(
+                    Operator::arbitrary(g),
+                    Motion::arbitrary(g),
+                    Option::<usize>::arbitrary(g),
+                ),
                 // ---
                 ToNormal,
                 ToInsert(Option::<usize>::arbitrary(g)),
@@ -872,15 +2790,85 @@ mod tests {
                 // ---
                 Yank,
                 Paste(Option::<Movement>::arbitrary(g)),
-                ClipboardCopy,
+                ClipboardCopy(bool::arbitrary(g)),
                 ClipboardPaste,
+                //Filter(String::arbitrary(g)),
+                Sort(bool::arbitrary(g)),
+                Reverse,
+                Case(bool::arbitrary(g)),
+                Swap(usize::arbitrary(g)),
+                Timestamp(*[4, 8].choose(g).unwrap()),
+                // ---
+                Search(Vec::<Option<u8>>::arbitrary(g)),
+                SearchNext(Movement::arbitrary(g)),
+                Find(u8::arbitrary(g)),
+                FindNext,
+                Jump(Movement::arbitrary(g)),
                 // ---
                 Undo,
                 Redo,
                 // ---
+                Set(Setting::arbitrary(g)),
+                SetMany(vec![Ok(Setting::arbitrary(g)), Err("no such option")]),
+                Map(Key::Char(char::arbitrary(g)), String::arbitrary(g)),
+                // ---
                 Show(String::arbitrary(g)),
+                Info,
+                Grep(String::arbitrary(g)),
+                List(Vec::<Option<u8>>::arbitrary(g)),
+                Note(Option::<String>::arbitrary(g)),
                 Redraw,
+                Repaint,
                 //Resize((u16::arbitrary(g), u16::arbitrary(g))), // FIXME
+                Click(u16::arbitrary(g), u16::arbitrary(g)),
+                SelectWord,
+                SelectRange(usize::arbitrary(g), usize::arbitrary(g)),
+                Help(bool::arbitrary(g)),
+                ScrollHelp(isize::arbitrary(g)),
+            ]
+            .choose(g)
+            .unwrap()
+            .clone()
+        }
+    }
+
+    impl Arbitrary for Setting {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            use Setting::*;
+            [
+                Snap(bool::arbitrary(g)),
+                Caret(Option::<CaretStyle>::arbitrary(g)),
+                Wrapscan(bool::arbitrary(g)),
+                Nibble(bool::arbitrary(g)),
+                NumberWidth(u16::arbitrary(g) % 17),
+                ErrorBell(bool::arbitrary(g)),
+                Tildes(bool::arbitrary(g)),
+                BackupCopy(bool::arbitrary(g)),
+                Setting::FileFormat(LineEnding::arbitrary(g)),
+                Setting::Clipboard(ClipboardKind::arbitrary(g)),
+                ScrollOff(usize::arbitrary(g)),
+                UndoLevels(usize::arbitrary(g)),
+                Undo(bool::arbitrary(g)),
+                IncSearch(bool::arbitrary(g)),
+                IgnoreCase(bool::arbitrary(g)),
+                ShowCmd(bool::arbitrary(g)),
+                Record(usize::arbitrary(g)),
+                Entropy(bool::arbitrary(g)),
+                LastStatus(bool::arbitrary(g)),
+                SwapFile(bool::arbitrary(g)),
+                HexCase(bool::arbitrary(g)),
+                CursorLine(bool::arbitrary(g)),
+                ColorColumn(Option::<usize>::arbitrary(g).map(|column| column % BYTES_PER_ROW)),
+                Setting::Encoding(crate::view::Encoding::arbitrary(g)),
+                Wrap(bool::arbitrary(g)),
+                MatchByte(bool::arbitrary(g)),
+                VirtualEdit(bool::arbitrary(g)),
+                Signed(bool::arbitrary(g)),
+                Setting::Endian(crate::controller::Endian::arbitrary(g)),
+                Mouse(bool::arbitrary(g)),
+                RelativeNumber(bool::arbitrary(g)),
+                Number(bool::arbitrary(g)),
+                AutoWrite(bool::arbitrary(g)),
             ]
             .choose(g)
             .unwrap()
@@ -888,6 +2876,57 @@ mod tests {
         }
     }
 
+    impl Arbitrary for CaretStyle {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            use CaretStyle::*;
+            *[Block, Bar, Underline].choose(g).unwrap()
+        }
+    }
+
+    impl Arbitrary for Encoding {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            use Encoding::*;
+            *[Ascii, Latin1, Cp437, Utf8].choose(g).unwrap()
+        }
+    }
+
+    impl Arbitrary for Endian {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            use Endian::*;
+            *[Little, Big].choose(g).unwrap()
+        }
+    }
+
+    impl Arbitrary for ClipboardKind {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            use ClipboardKind::*;
+            *[System, Osc52].choose(g).unwrap()
+        }
+    }
+
+    impl Arbitrary for LineEnding {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            use LineEnding::*;
+            *[Unix, Dos, Mac].choose(g).unwrap()
+        }
+    }
+
+    impl Arbitrary for Operator {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            use Operator::*;
+            *[Delete, Yank].choose(g).unwrap()
+        }
+    }
+
+    impl Arbitrary for Motion {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            use Motion::*;
+            *[Byte, WordStart, WordEnd, RowStart, RowEnd, BufferEnd, Row]
+                .choose(g)
+                .unwrap()
+        }
+    }
+
     impl Arbitrary for Movement {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
             use Movement::*;
@@ -905,6 +2944,8 @@ mod tests {
                 Down,
                 //Start,
                 Offset(usize::arbitrary(g)),
+                Relative(isize::arbitrary(g)),
+                Percent(usize::arbitrary(g)),
                 //End,
                 Newline,
                 Revert,
@@ -918,8 +2959,1372 @@ mod tests {
     impl Arbitrary for InputMode {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
             use InputMode::*;
-            [Ascii, Hex].choose(g).unwrap().clone()
+            *[Ascii, Hex].choose(g).unwrap()
+        }
+    }
+
+    // Controller drawing into memory with `buffer` loaded
+    fn headless(buffer: Vec<u8>) -> Controller {
+        let mut model = Model::new();
+        model.caret = Caret::Offset(UsizeMax::new(0, buffer.len().saturating_sub(1)));
+        model.buffer = buffer.into();
+
+        let mut ctrl = Controller::new(model, View::new(Rc::new(RefCell::new(Vec::new()))));
+        ctrl.update(Msg::Resize((80, 24)));
+        ctrl
+    }
+
+    impl Controller {
+        // Types `keys` like a terminal reports them, e.g. `"ihh\x1b:q\n"`: `\x1b` is Esc, `\x7f`
+        // Backspace and the other control characters are Ctrl keys. Stops once a key quits.
+        fn feed_keys(&mut self, keys: &str) -> bool {
+            keys.chars().all(|c| {
+                let key = match c {
+                    '\x1b' => Key::Esc,
+                    '\x7f' => Key::Backspace,
+                    '\0' => Key::Null,
+                    '\r' => Key::Char('\n'),
+                    '\n' | '\t' => Key::Char(c),
+                    '\x01'..='\x1a' => Key::Ctrl((c as u8 - 0x01 + b'a') as char),
+                    '\x1c'..='\x1f' => Key::Ctrl((c as u8 - 0x1c + b'4') as char),
+                    _ => Key::Char(c),
+                };
+                self.transition(key)
+            })
+        }
+    }
+
+    #[test]
+    fn test_list() {
+        let mut buffer = vec![0; 64];
+        for offset in [3, 0x10, 0x2a] {
+            buffer[offset] = 0xab;
         }
+        buffer[0x11] = 0xcd;
+        let mut ctrl = headless(buffer);
+
+        ctrl.execute("list ab");
+        assert_eq!(ctrl.view.status_view.body, "3 matches: 0x3, 0x10, 0x2a");
+        assert_eq!(ctrl.model.get_index(), 0);
+        ctrl.execute("list ab cd");
+        assert_eq!(ctrl.view.status_view.body, "1 match: 0x10");
+        ctrl.execute("list ef");
+        assert_eq!(ctrl.view.status_view.body, "pattern not found: ef");
+
+        ctrl.execute("list 00 00");
+        assert_eq!(
+            ctrl.view.status_view.body,
+            "56 matches: 0x0, 0x1, 0x4, 0x5, 0x6, 0x7, 0x8, 0x9, 0xa, 0xb, 0xc, 0xd, 0xe, 0x12, \
+             0x13, 0x14, and 40 more"
+        );
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let mut ctrl = headless(vec![0xff]);
+        let before = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        ctrl.execute("timestamp");
+        assert_eq!(ctrl.model.len(), 5);
+        assert_eq!(ctrl.model.get_index(), 3);
+        let secs = u32::from_le_bytes(<[u8; 4]>::try_from(&*ctrl.model.bytes(0..4)).unwrap());
+        assert!((before..before + 60).contains(&(secs as u64)));
+
+        ctrl.execute("set endian big");
+        ctrl.execute("timestamp 8");
+        assert_eq!(ctrl.model.len(), 13);
+        let secs = u64::from_be_bytes(<[u8; 8]>::try_from(&*ctrl.model.bytes(3..11)).unwrap());
+        assert!((before..before + 60).contains(&secs));
+        assert_eq!(ctrl.model.byte_at(12), Some(0xff));
+    }
+
+    #[test]
+    fn test_virtualedit() {
+        let mut ctrl = headless(vec![0xaa, 0xbb]);
+        ctrl.execute("set virtualedit on");
+
+        ctrl.feed_keys("lll");
+        assert_eq!(ctrl.model.get_index(), 2);
+        ctrl.update(Msg::Redraw);
+
+        // Replacing the end appends, deleting it does nothing
+        ctrl.feed_keys("rcc");
+        assert_eq!(ctrl.model.buffer, vec![0xaa, 0xbb, 0xcc]);
+        assert_eq!(ctrl.model.get_index(), 2);
+        ctrl.feed_keys("lx");
+        assert_eq!(ctrl.model.buffer, vec![0xaa, 0xbb, 0xcc]);
+        ctrl.feed_keys("idd\x1b");
+        assert_eq!(ctrl.model.buffer, vec![0xaa, 0xbb, 0xcc, 0xdd]);
+        assert_eq!(ctrl.model.get_index(), 3);
+
+        // A selection stops at the last byte
+        ctrl.feed_keys("lv");
+        assert_eq!(ctrl.selection(), Some((3, 3)));
+        ctrl.feed_keys("\x1bl");
+        assert_eq!(ctrl.model.get_index(), 4);
+
+        // There is no byte to copy, yank or note at the end
+        ctrl.clipboard = Clipboard::Session(vec![]);
+        ctrl.yank = Some(vec![0xee]);
+        ctrl.feed_keys("\x03yl");
+        assert!(matches!(ctrl.clipboard, Clipboard::Session(ref bytes) if bytes.is_empty()));
+        assert_eq!(ctrl.yank, Some(vec![0xee]));
+        ctrl.execute("note end");
+        assert_eq!(ctrl.view.status_view.body, "no byte to note");
+        assert!(ctrl.model.notes.is_empty());
+
+        ctrl.execute("set virtualedit off");
+        assert_eq!(ctrl.model.get_index(), 3);
+        ctrl.feed_keys("l");
+        assert_eq!(ctrl.model.get_index(), 3);
+    }
+
+    #[test]
+    fn test_feed_keys() {
+        let mut ctrl = headless(vec![]);
+        ctrl.snapshot();
+
+        assert!(ctrl.feed_keys("iab\x1bu"));
+        assert!(ctrl.model.is_empty());
+        assert!(ctrl.feed_keys("\x12"));
+        assert_eq!(ctrl.model.buffer, vec![0xab]);
+        assert!(matches!(ctrl.state, VimState::Normal));
+
+        // The keys after quitting are left alone
+        assert!(!ctrl.feed_keys(":q!\nx"));
+        assert_eq!(ctrl.model.buffer, vec![0xab]);
+    }
+
+    #[test]
+    fn test_search_wrapscan() {
+        let mut ctrl = headless(vec![0xaa, 0, 0, 0xaa, 0]);
+
+        ctrl.update(Msg::Search(vec![Some(0xaa)]));
+        assert_eq!(ctrl.model.get_index(), 3);
+        assert_eq!(ctrl.view.status_view.body, "/aa [2/2]");
+        ctrl.update(Msg::SearchNext(Movement::Right));
+        assert_eq!(ctrl.model.get_index(), 0);
+        assert!(ctrl.view.status_view.body.ends_with("[1/2]"));
+        ctrl.update(Msg::SearchNext(Movement::Left));
+        assert_eq!(ctrl.model.get_index(), 3);
+
+        ctrl.update(Msg::Set(Setting::Wrapscan(false)));
+        ctrl.update(Msg::SearchNext(Movement::Right));
+        assert_eq!(ctrl.model.get_index(), 3);
+        assert!(ctrl
+            .view
+            .status_view
+            .body
+            .contains("hit BOTTOM without match"));
+    }
+
+    #[test]
+    fn test_visual_relative_goto() {
+        let mut ctrl = headless((0..64).collect());
+
+        for key in ":4\nv:+16\n".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        assert!(matches!(ctrl.state, VimState::Visual));
+        assert_eq!(ctrl.selection(), Some((4, 20)));
+
+        for key in ":-0x8\n".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        assert!(matches!(ctrl.state, VimState::Visual));
+        assert_eq!(ctrl.selection(), Some((4, 12)));
+    }
+
+    #[test]
+    fn test_sort() {
+        let mut ctrl = headless(vec![9, 3, 7, 1, 5, 0]);
+
+        ctrl.update(Msg::Sort(false));
+        assert_eq!(ctrl.model.buffer, vec![9, 3, 7, 1, 5, 0]);
+
+        for key in "lvlll:sort\n".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        assert_eq!(ctrl.model.buffer, vec![9, 1, 3, 5, 7, 0]);
+        assert!(matches!(ctrl.state, VimState::Visual));
+
+        for key in ":sort!\n".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        assert_eq!(ctrl.model.buffer, vec![9, 7, 5, 3, 1, 0]);
+
+        ctrl.update(Msg::Undo);
+        assert_eq!(ctrl.model.buffer, vec![9, 1, 3, 5, 7, 0]);
+    }
+
+    #[test]
+    fn test_reverse() {
+        let mut ctrl = headless(vec![0, 1, 2, 3, 4]);
+
+        ctrl.update(Msg::Reverse);
+        assert_eq!(ctrl.model.buffer, vec![4, 3, 2, 1, 0]);
+
+        for key in "lvll:reverse\n".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        assert_eq!(ctrl.model.buffer, vec![4, 1, 2, 3, 0]);
+        assert_eq!(ctrl.selection(), Some((1, 3)));
+    }
+
+    #[test]
+    fn test_swap() {
+        let mut ctrl = headless(vec![0xaa, 0xbb, 0xcc, 0xdd, 0x11, 0x22, 0x33, 0x44, 0x55]);
+
+        ctrl.update(Msg::Swap(4));
+        assert_eq!(
+            ctrl.model.buffer,
+            vec![0xdd, 0xcc, 0xbb, 0xaa, 0x44, 0x33, 0x22, 0x11, 0x55]
+        );
+        assert!(ctrl.view.status_view.body.contains("1 trailing bytes"));
+
+        ctrl.update(Msg::Swap(0));
+        assert_eq!(
+            ctrl.model.buffer,
+            vec![0xdd, 0xcc, 0xbb, 0xaa, 0x44, 0x33, 0x22, 0x11, 0x55]
+        );
+    }
+
+    #[test]
+    fn test_errorbell() {
+        let mut ctrl = headless(vec![0]);
+
+        ctrl.transition(Key::Char('Z'));
+        assert_eq!(ctrl.view.status_view.body, "");
+
+        ctrl.update(Msg::Set(Setting::ErrorBell(true)));
+        ctrl.transition(Key::Char('Z'));
+        assert_eq!(ctrl.view.status_view.body, "invalid input");
+    }
+
+    #[test]
+    fn test_map() {
+        let mut ctrl = headless(vec![0; 32]);
+
+        for key in ":map x :0x10\n".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+
+        // The mapping shadows the built-in deletion
+        ctrl.transition(Key::Char('x'));
+        assert_eq!(ctrl.model.get_index(), 0x10);
+        assert_eq!(ctrl.model.buffer.len(), 32);
+    }
+
+    #[test]
+    fn test_counted_insert() {
+        let mut ctrl = headless(vec![0x00, 0x11]);
+
+        for key in "l3iff".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        ctrl.transition(Key::Esc);
+        assert_eq!(ctrl.model.buffer, vec![0x00, 0xff, 0xff, 0xff, 0x11]);
+
+        // The count is gone after use...
+        for key in "iee".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        ctrl.transition(Key::Esc);
+        assert_eq!(ctrl.model.buffer, vec![0x00, 0xff, 0xff, 0xee, 0xff, 0x11]);
+
+        // ...and the repetitions are a single undo step.
+        ctrl.update(Msg::Undo);
+        ctrl.update(Msg::Undo);
+        assert_eq!(ctrl.model.buffer, vec![0x00, 0xff, 0x11]);
+    }
+
+    #[test]
+    fn test_fileformat() {
+        let mut ctrl = headless(vec![0x00]);
+
+        ctrl.update(Msg::Switch(Some(InputMode::Ascii)));
+        for key in "ia\n".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        ctrl.update(Msg::Set(Setting::FileFormat(LineEnding::Dos)));
+        for key in "b\n".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        ctrl.transition(Key::Esc);
+        assert_eq!(ctrl.model.buffer, b"a\nb\r\n\x00".to_vec());
+
+        // Enter still isn't a hex digit
+        ctrl.update(Msg::Switch(Some(InputMode::Hex)));
+        ctrl.transition(Key::Char('i'));
+        ctrl.transition(Key::Char('\n'));
+        ctrl.transition(Key::Esc);
+        assert_eq!(ctrl.model.buffer.len(), 6);
+    }
+
+    #[test]
+    fn test_insert_at_ends() {
+        let mut ctrl = headless(vec![1, 2, 3]);
+
+        ctrl.update(Msg::Move(Direction::Offset(1)));
+        for key in "Aff".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        ctrl.transition(Key::Esc);
+        assert_eq!(ctrl.model.buffer, vec![1, 2, 3, 0xff]);
+
+        ctrl.update(Msg::Move(Direction::Offset(2)));
+        ctrl.transition(Key::Char('g'));
+        ctrl.transition(Key::Char('I'));
+        for key in "7f".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        ctrl.transition(Key::Esc);
+        assert_eq!(ctrl.model.buffer, vec![0x7f, 1, 2, 3, 0xff]);
+
+        // Anything else after the prefix is rejected
+        ctrl.transition(Key::Char('g'));
+        ctrl.transition(Key::Char('x'));
+        assert!(matches!(ctrl.state, VimState::Normal));
+        assert_eq!(ctrl.model.buffer.len(), 5);
+    }
+
+    #[test]
+    fn test_literal_insert() {
+        let mut ctrl = headless(vec![]);
+
+        ctrl.update(Msg::Switch(Some(InputMode::Ascii)));
+        ctrl.transition(Key::Char('i'));
+        ctrl.transition(Key::Char(' '));
+        ctrl.transition(Key::Ctrl('q'));
+        for key in "1b".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        ctrl.transition(Key::Char('x'));
+        ctrl.transition(Key::Esc);
+        assert_eq!(ctrl.model.buffer, vec![0x20, 0x1b, b'x']);
+    }
+
+    #[test]
+    fn test_find() {
+        let mut ctrl = headless(vec![0xaa, 0x01, 0xaa, 0x02, 0xaa]);
+
+        for key in "faa".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        assert_eq!(ctrl.model.get_index(), 2);
+        ctrl.transition(Key::Char(';'));
+        assert_eq!(ctrl.model.get_index(), 4);
+
+        // No wrap around at the end of the buffer
+        ctrl.transition(Key::Char(';'));
+        assert_eq!(ctrl.model.get_index(), 4);
+        assert!(ctrl.view.status_view.body.contains("not found"));
+
+        // A half-typed byte is dropped on Esc
+        ctrl.transition(Key::Char('f'));
+        ctrl.transition(Key::Char('0'));
+        ctrl.transition(Key::Esc);
+        ctrl.transition(Key::Char('0'));
+        assert_eq!(ctrl.model.get_index(), 4);
+    }
+
+    #[test]
+    fn test_esc() {
+        let mut ctrl = headless(vec![0x00, 0x11, 0x22, 0x33]);
+
+        // Half-typed bytes are discarded...
+        for keys in &["if", "af", "rf", "Rf"] {
+            for key in keys.chars() {
+                ctrl.transition(Key::Char(key));
+            }
+            ctrl.transition(Key::Esc);
+            assert!(matches!(ctrl.state, VimState::Normal));
+            assert_eq!(ctrl.model.buffer, vec![0x00, 0x11, 0x22, 0x33]);
+        }
+
+        // ...and so is a pending count
+        ctrl.transition(Key::Char('3'));
+        ctrl.transition(Key::Esc);
+        ctrl.transition(Key::Char('i'));
+        for key in "ff".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        ctrl.transition(Key::Esc);
+        assert_eq!(ctrl.model.buffer.len(), 5);
+
+        // Commands are abandoned as well
+        for key in ":q".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        assert!(ctrl.transition(Key::Esc));
+        assert!(matches!(ctrl.state, VimState::Normal));
+    }
+
+    #[test]
+    fn test_scrolloff() {
+        let mut ctrl = headless(vec![0; 1024]);
+        ctrl.update(Msg::Set(Setting::ScrollOff(3)));
+
+        // The view is 21 rows high, the caret may go down to row 17
+        ctrl.update(Msg::Move(Direction::Offset(17 * 16)));
+        assert_eq!(ctrl.view.hex_view.get_scroll_start(), 0);
+        ctrl.update(Msg::Move(Direction::Down));
+        assert_eq!(ctrl.view.hex_view.get_scroll_start(), 16);
+
+        ctrl.update(Msg::Move(Direction::Offset(4 * 16)));
+        assert_eq!(ctrl.view.hex_view.get_scroll_start(), 16);
+        ctrl.update(Msg::Move(Direction::Up));
+        assert_eq!(ctrl.view.hex_view.get_scroll_start(), 0);
+    }
+
+    #[test]
+    fn test_jumps() {
+        let mut ctrl = headless(vec![0; 256]);
+
+        ctrl.update(Msg::Move(Direction::Offset(100)));
+        ctrl.update(Msg::Move(Direction::Right));
+        ctrl.update(Msg::Move(Direction::Relative(-51)));
+        assert_eq!(ctrl.model.get_index(), 50);
+
+        // Ordinary motions aren't recorded
+        ctrl.transition(Key::Ctrl('o'));
+        assert_eq!(ctrl.model.get_index(), 101);
+        ctrl.transition(Key::Ctrl('o'));
+        assert_eq!(ctrl.model.get_index(), 0);
+        ctrl.transition(Key::Ctrl('o'));
+        assert_eq!(ctrl.model.get_index(), 0);
+
+        ctrl.transition(Key::Ctrl('i'));
+        ctrl.transition(Key::Ctrl('i'));
+        assert_eq!(ctrl.model.get_index(), 50);
+        ctrl.transition(Key::Ctrl('i'));
+        assert_eq!(ctrl.model.get_index(), 50);
+
+        // A new jump drops the newer entries
+        ctrl.transition(Key::Ctrl('o'));
+        ctrl.update(Msg::Move(Direction::Offset(200)));
+        ctrl.transition(Key::Ctrl('o'));
+        assert_eq!(ctrl.model.get_index(), 101);
+        ctrl.transition(Key::Ctrl('i'));
+        assert_eq!(ctrl.model.get_index(), 200);
+    }
+
+    #[test]
+    fn test_clipboard_limit() {
+        let mut ctrl = headless(vec![0; CLIPBOARD_LIMIT + 1]);
+
+        ctrl.update(Msg::ToVisual);
+        ctrl.update(Msg::Move(Direction::Offset(CLIPBOARD_LIMIT)));
+        ctrl.update(Msg::ClipboardCopy(false));
+        assert!(ctrl.view.status_view.body.contains(":copy!"));
+    }
+
+    #[test]
+    fn test_session_clipboard() {
+        let mut ctrl = headless(vec![1, 2, 3, 4]);
+        ctrl.clipboard = Clipboard::Session(vec![]);
+
+        ctrl.update(Msg::ClipboardPaste);
+        assert!(ctrl
+            .view
+            .status_view
+            .body
+            .contains("session clipboard is empty"));
+
+        ctrl.update(Msg::ToVisual);
+        ctrl.update(Msg::Move(Direction::Right));
+        ctrl.update(Msg::ClipboardCopy(false));
+        assert_eq!(
+            ctrl.view.status_view.body,
+            "Copied to the session clipboard (0102...), no system clipboard found"
+        );
+
+        ctrl.update(Msg::ToNormal);
+        ctrl.update(Msg::Move(Direction::Offset(2)));
+        ctrl.update(Msg::ToVisual);
+        ctrl.update(Msg::Move(Direction::Right));
+        ctrl.update(Msg::ClipboardPaste);
+        assert_eq!(ctrl.model.buffer, vec![1, 2, 1, 2]);
+    }
+
+    #[test]
+    fn test_osc52_clipboard() {
+        let screen = Rc::new(RefCell::new(Vec::new()));
+        let mut model = Model::new();
+        model.buffer = vec![0xab, 0xcd].into();
+        model.caret = Caret::Offset(UsizeMax::new(0, 1));
+        let mut ctrl = Controller::new(model, View::new(screen.clone()));
+        ctrl.update(Msg::Resize((80, 24)));
+        ctrl.execute("set clipboard osc52");
+
+        ctrl.update(Msg::ClipboardPaste);
+        assert!(ctrl.view.status_view.body.contains("can't be read"));
+
+        screen.borrow_mut().clear();
+        ctrl.update(Msg::ClipboardCopy(false));
+        assert!(String::from_utf8_lossy(&screen.borrow()).contains("\x1b]52;c;YWI=\x07"));
+        assert_eq!(
+            ctrl.view.status_view.body,
+            "Copied to the terminal's clipboard (ab)"
+        );
+
+        // Pastes what was copied last
+        ctrl.update(Msg::Move(Direction::Right));
+        ctrl.update(Msg::ClipboardPaste);
+        assert_eq!(ctrl.model.buffer, vec![0xab, 0xab, 0xcd]);
+    }
+
+    #[test]
+    fn test_open_progress() {
+        let file = TempFile::new("open");
+        let path = file.path();
+        fs::write(path, vec![0; 2 * READ_CHUNK]).unwrap();
+
+        let screen = Rc::new(RefCell::new(Vec::new()));
+        let mut ctrl = Controller::new(Model::new(), View::new(screen.clone()));
+        ctrl.update(Msg::Resize((80, 24)));
+        ctrl.open(path);
+
+        let screen = String::from_utf8_lossy(&screen.borrow()).to_string();
+        assert!(screen.contains(&format!("({} bytes) | 50%", 2 * READ_CHUNK)));
+        assert!(screen.contains("/ 100%"));
+        assert_eq!(ctrl.model.len(), 2 * READ_CHUNK);
+        assert_eq!(ctrl.view.status_view.body, "");
+    }
+
+    #[test]
+    fn test_open_autowrite() {
+        let (first, second) = (TempFile::new("autowrite-1"), TempFile::new("autowrite-2"));
+        let (first, second) = (first.path(), second.path());
+        fs::write(first, [1, 2]).unwrap();
+        fs::write(second, [3]).unwrap();
+
+        let mut ctrl = headless(vec![]);
+        ctrl.update(Msg::Open(first.into()));
+        ctrl.update(Msg::Delete(Some(Movement::Right)));
+
+        // Modified buffers aren't left behind...
+        ctrl.update(Msg::Open(second.into()));
+        assert_eq!(ctrl.model.path, first);
+        assert!(ctrl.view.status_view.body.contains("unsaved changes"));
+
+        // ...unless they may be saved first
+        ctrl.update(Msg::Set(Setting::AutoWrite(true)));
+        ctrl.update(Msg::Open(second.into()));
+        assert_eq!(ctrl.model.path, second);
+        assert_eq!(ctrl.model.buffer, vec![3]);
+        assert_eq!(fs::read(first).unwrap(), vec![2]);
+
+        // A failing save keeps the buffer open
+        ctrl.model.path = env::temp_dir()
+            .join("nonexistent")
+            .join("file")
+            .to_str()
+            .unwrap()
+            .into();
+        ctrl.update(Msg::Open(first.into()));
+        assert!(ctrl.view.status_view.body.starts_with("could not save"));
+        assert_eq!(ctrl.model.buffer, vec![3]);
+    }
+
+    #[test]
+    fn test_export() {
+        let file = TempFile::new("export");
+        let path = file.path().to_string();
+
+        let mut ctrl = headless(b"abcdef".to_vec());
+        ctrl.update(Msg::Export(path.clone(), Some(4), false));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "00000000: 6162 6364  abcd\n00000004: 6566       ef\n"
+        );
+
+        // Only the selection is exported, and only with ! over the existing dump
+        ctrl.update(Msg::ToVisual);
+        ctrl.update(Msg::Move(Direction::Right));
+        ctrl.update(Msg::Export(path.clone(), None, false));
+        assert!(ctrl.view.status_view.body.contains("exists"));
+        ctrl.update(Msg::Export(path.clone(), Some(2), true));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "00000000: 6162  ab\n");
+    }
+
+    #[test]
+    fn test_record() {
+        let mut ctrl = headless(vec![0; 64]);
+
+        ctrl.update(Msg::Set(Setting::Record(5)));
+        ctrl.update(Msg::Move(Direction::Down));
+        ctrl.update(Msg::Move(Direction::Down));
+        assert_eq!(ctrl.model.get_index(), 10);
+        ctrl.update(Msg::Move(Direction::Up));
+        assert_eq!(ctrl.model.get_index(), 5);
+
+        ctrl.update(Msg::Set(Setting::Record(0)));
+        ctrl.update(Msg::Move(Direction::Down));
+        assert_eq!(ctrl.model.get_index(), 21);
+    }
+
+    #[test]
+    fn test_info() {
+        let mut ctrl = headless(vec![0x00, 0x41, 0x41, 0x00, 0x41, 0x00, 0x00, 0xff]);
+        ctrl.model.path = "missing.bin".into();
+
+        ctrl.update(Msg::Move(Direction::Offset(2)));
+        assert_eq!(
+            ctrl.info(),
+            "\"missing.bin\" [Modified] 8 bytes, offset 0x2, 50.0% zero, most common 0x00 (50.0%)"
+        );
+
+        ctrl.update(Msg::ToVisual);
+        ctrl.update(Msg::Move(Direction::Right));
+        assert!(ctrl.info().contains("offset 0x3, 2 selected"));
+
+        // Ties go to the smaller byte
+        ctrl.model.buffer = vec![0x41, 0x20, 0x20, 0x41].into();
+        assert!(ctrl.info().contains("most common 0x20 (50.0%)"));
+    }
+
+    #[test]
+    fn test_percent() {
+        let mut ctrl = headless(vec![0; 200]);
+
+        ctrl.update(Msg::Move(Direction::Percent(50)));
+        assert_eq!(ctrl.model.get_index(), 100);
+        ctrl.update(Msg::Move(Direction::Percent(0)));
+        assert_eq!(ctrl.model.get_index(), 0);
+
+        // Both the percentage and the offset are clamped
+        ctrl.update(Msg::Move(Direction::Percent(100)));
+        assert_eq!(ctrl.model.get_index(), 199);
+        ctrl.update(Msg::Move(Direction::Percent(usize::MAX)));
+        assert_eq!(ctrl.model.get_index(), 199);
+        assert_eq!(ctrl.view.status_view.body, "");
+
+        // It's a jump
+        ctrl.transition(Key::Ctrl('o'));
+        assert_eq!(ctrl.model.get_index(), 0);
+    }
+
+    #[test]
+    fn test_count_goto() {
+        let mut ctrl = headless(vec![0; 200]);
+
+        ctrl.transition(Key::Char('G'));
+        assert_eq!(ctrl.model.get_index(), 199);
+
+        for key in "42G".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        assert_eq!(ctrl.model.get_index(), 42);
+        assert!(ctrl.view.status_view.body.starts_with("byte 42 (0x2a)"));
+
+        for key in "999G".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        assert_eq!(ctrl.model.get_index(), 199);
+        assert!(ctrl.view.status_view.body.starts_with("offset beyond end"));
+
+        // Both are jumps
+        ctrl.transition(Key::Ctrl('o'));
+        assert_eq!(ctrl.model.get_index(), 42);
+    }
+
+    #[test]
+    fn test_offset_beyond_end() {
+        let mut ctrl = headless(vec![0; 200]);
+
+        ctrl.execute("0xffffffffffffffff");
+        assert_eq!(ctrl.model.get_index(), 199);
+        assert_eq!(
+            ctrl.view.status_view.body,
+            "offset beyond end of file (clamped to 0xc7)"
+        );
+
+        ctrl.execute("0xc7");
+        assert_eq!(ctrl.view.status_view.body, "");
+
+        // Too far to jump relatively, instead of wrapping around to -1
+        ctrl.execute("10");
+        ctrl.execute("+0xffffffffffffffff");
+        assert_eq!(ctrl.model.get_index(), 10);
+        assert_eq!(ctrl.view.status_view.body, "relative offset out of range");
+    }
+
+    #[test]
+    fn test_case() {
+        let mut ctrl = headless(b"aB1-cD\xe4z".to_vec());
+
+        for key in "lvlllllU".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        assert_eq!(ctrl.model.buffer, b"aB1-CD\xe4z".to_vec());
+        assert!(matches!(ctrl.state, VimState::Normal));
+        assert_eq!(ctrl.model.get_index(), 1);
+
+        for key in "vllllllu".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        assert_eq!(ctrl.model.buffer, b"ab1-cd\xe4z".to_vec());
+
+        // A single undo step each
+        ctrl.update(Msg::Undo);
+        assert_eq!(ctrl.model.buffer, b"aB1-CD\xe4z".to_vec());
+    }
+
+    #[test]
+    fn test_delete_motions() {
+        let keys = |ctrl: &mut Controller, keys: &str| {
+            for key in keys.chars() {
+                ctrl.transition(Key::Char(key));
+            }
+        };
+
+        let mut ctrl = headless(vec![1, 2, 0, 0, 3, 4, 0, 5]);
+        keys(&mut ctrl, "ldw");
+        assert_eq!(ctrl.model.buffer, vec![1, 3, 4, 0, 5]);
+        assert_eq!(ctrl.yank, Some(vec![2, 0, 0]));
+        keys(&mut ctrl, "de");
+        assert_eq!(ctrl.model.buffer, vec![1, 0, 5]);
+        assert!(matches!(ctrl.state, VimState::Normal));
+
+        let mut ctrl = headless((0..40).collect());
+        ctrl.update(Msg::Move(Direction::Offset(20)));
+        keys(&mut ctrl, "d0");
+        assert_eq!(
+            ctrl.model.buffer,
+            (0..16).chain(20..40).collect::<Vec<u8>>()
+        );
+        assert_eq!(ctrl.model.get_index(), 16);
+        keys(&mut ctrl, "d$");
+        assert_eq!(
+            ctrl.model.buffer,
+            (0..16).chain(36..40).collect::<Vec<u8>>()
+        );
+        keys(&mut ctrl, "dd");
+        assert_eq!(ctrl.model.buffer, (0..16).collect::<Vec<u8>>());
+        ctrl.update(Msg::Move(Direction::Offset(4)));
+        keys(&mut ctrl, "dG");
+        assert_eq!(ctrl.model.buffer, vec![0, 1, 2, 3]);
+
+        // Anything but a motion cancels the operator
+        keys(&mut ctrl, "dxu");
+        assert_eq!(ctrl.model.buffer, (0..16).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_counted_operators() {
+        let mut ctrl = headless(vec![1, 0, 2, 0, 3, 0, 4]);
+        ctrl.feed_keys("2dw");
+        assert_eq!(ctrl.model.buffer, vec![3, 0, 4]);
+        // Past the last word, the count is cut short
+        ctrl.feed_keys("9dw");
+        assert_eq!(ctrl.model.buffer, vec![]);
+
+        let mut ctrl = headless(vec![1, 1, 0, 2, 2, 0, 3]);
+        ctrl.feed_keys("2de");
+        assert_eq!(ctrl.model.buffer, vec![0, 3]);
+
+        let mut ctrl = headless((0..64).collect());
+        ctrl.update(Msg::Move(Direction::Offset(20)));
+        ctrl.feed_keys("2yy");
+        assert_eq!(ctrl.yank, Some((16..48).collect()));
+        ctrl.feed_keys("3yl");
+        assert_eq!(ctrl.yank, Some(vec![16, 17, 18]));
+        ctrl.feed_keys("2d$");
+        assert_eq!(
+            ctrl.model.buffer,
+            (0..16).chain(48..64).collect::<Vec<u8>>()
+        );
+        ctrl.feed_keys("9dd");
+        assert_eq!(ctrl.model.buffer, (0..16).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_yank_motions() {
+        let keys = |ctrl: &mut Controller, keys: &str| {
+            for key in keys.chars() {
+                ctrl.transition(Key::Char(key));
+            }
+        };
+
+        let mut ctrl = headless((1..=40).collect());
+        ctrl.update(Msg::Move(Direction::Offset(20)));
+        keys(&mut ctrl, "y$");
+        assert_eq!(ctrl.yank, Some((21..=32).collect()));
+        keys(&mut ctrl, "y0");
+        assert_eq!(ctrl.yank, Some((17..=20).collect()));
+        assert_eq!(ctrl.model.get_index(), 16);
+        keys(&mut ctrl, "yy");
+        assert_eq!(ctrl.yank, Some((17..=32).collect()));
+        keys(&mut ctrl, "yl");
+        assert_eq!(ctrl.yank, Some(vec![17]));
+        keys(&mut ctrl, "yw");
+        assert_eq!(ctrl.yank, Some((17..=40).collect()));
+
+        // Nothing is removed
+        assert_eq!(ctrl.model.len(), 40);
+        assert!(matches!(ctrl.state, VimState::Normal));
+    }
+
+    #[test]
+    fn test_log() {
+        let path = TempFile::new("log");
+        let mut ctrl = headless(vec![0; 4]);
+        ctrl.set_log(Box::new(fs::File::create(&path).unwrap()));
+
+        ctrl.transition(Key::Char('l'));
+        ctrl.transition(Key::Char('d'));
+        drop(ctrl);
+
+        let log = fs::read_to_string(&path).unwrap();
+        let entries: Vec<_> = log
+            .lines()
+            .map(|line| line.split_once(' ').unwrap().1)
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                "key Char('l') in Normal",
+                "msg Move(Right)",
+                "state Normal",
+                "key Char('d') in Normal",
+                "msg Show(\"d\")",
+                "state Operator(Delete)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_click() {
+        let mut ctrl = headless(vec![0; 64]);
+
+        // The hex pane starts at column 5 with two offset digits, the first row at line 2
+        ctrl.update(Msg::Click(5 + 3 * 3, 3));
+        assert_eq!(ctrl.model.get_index(), 19);
+
+        // Neither on the offsets, nor with the mouse off, nor while typing a command
+        ctrl.update(Msg::Click(1, 2));
+        assert_eq!(ctrl.model.get_index(), 19);
+        ctrl.execute("set mouse off");
+        ctrl.update(Msg::Click(5, 2));
+        assert_eq!(ctrl.model.get_index(), 19);
+        ctrl.execute("set mouse on");
+        ctrl.transition(Key::Char(':'));
+        ctrl.update(Msg::Click(5, 2));
+        assert_eq!(ctrl.model.get_index(), 19);
+    }
+
+    #[test]
+    fn test_paste_over_selection() {
+        let mut ctrl = headless(vec![0, 1, 2, 3, 4]);
+        ctrl.snapshot();
+
+        ctrl.update(Msg::Move(Direction::Offset(1)));
+        ctrl.update(Msg::ToVisual);
+        ctrl.update(Msg::Move(Direction::Right));
+        ctrl.update(Msg::Move(Direction::Right));
+        ctrl.paste_over_selection(&[0xaa, 0xbb]);
+        ctrl.snapshot();
+        assert_eq!(ctrl.model.buffer, vec![0, 0xaa, 0xbb, 4]);
+        assert_eq!(ctrl.model.get_index(), 2);
+        assert!(matches!(ctrl.model.caret, Caret::Offset(_)));
+
+        // A single undo step
+        ctrl.update(Msg::Undo);
+        assert_eq!(ctrl.model.buffer, vec![0, 1, 2, 3, 4]);
+
+        // Without a selection, the bytes are inserted
+        ctrl.update(Msg::ToInsert(None));
+        ctrl.paste_over_selection(&[0xcc]);
+        assert_eq!(ctrl.model.len(), 6);
+    }
+
+    #[test]
+    fn test_edit_external() {
+        let mut ctrl = headless(vec![0, 1, 2, 3]);
+        ctrl.snapshot();
+
+        ctrl.update(Msg::Move(Direction::Offset(2)));
+        ctrl.edit_external("printf 'ab' >>");
+        assert_eq!(ctrl.model.buffer, vec![0, 1, 2, 3, b'a', b'b']);
+        assert_eq!(ctrl.model.get_index(), 2);
+        assert!(ctrl.view.status_view.body.starts_with("6 bytes read back"));
+
+        ctrl.update(Msg::Undo);
+        assert_eq!(ctrl.model.buffer, vec![0, 1, 2, 3]);
+
+        ctrl.edit_external("true");
+        assert_eq!(ctrl.view.status_view.body, "no changes");
+
+        // Failing editors leave the buffer alone
+        ctrl.edit_external("printf 'ab' > \"$1\"; false");
+        assert_eq!(
+            ctrl.view.status_view.body,
+            "\"printf 'ab' > \"$1\"; false\" exited with 1"
+        );
+        assert_eq!(ctrl.model.buffer, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_append() {
+        let file = TempFile::new("append-cmd");
+        let path = file.path();
+        fs::write(path, [0xff]).unwrap();
+
+        let mut ctrl = headless(vec![0, 1, 2, 3]);
+        ctrl.execute(&format!("w >> {}", path));
+        assert_eq!(
+            ctrl.view.status_view.body,
+            format!("4 bytes appended to \"{}\"", path)
+        );
+
+        ctrl.update(Msg::Move(Direction::Offset(1)));
+        ctrl.update(Msg::ToVisual);
+        ctrl.update(Msg::Move(Direction::Right));
+        ctrl.execute(&format!("w>>{}", path));
+        assert_eq!(fs::read(path).unwrap(), vec![0xff, 0, 1, 2, 3, 1, 2]);
+        assert_eq!(ctrl.model.buffer, vec![0, 1, 2, 3]);
+
+        fs::remove_file(path).unwrap();
+        ctrl.execute(&format!("w >> {}", path));
+        assert!(ctrl.view.status_view.body.starts_with("could not append"));
+    }
+
+    #[test]
+    fn test_undolevels() {
+        let mut ctrl = headless(vec![]);
+        ctrl.snapshot();
+        ctrl.execute("set undolevels 2");
+
+        ctrl.transition(Key::Char('i'));
+        for key in "0102030405".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        ctrl.transition(Key::Esc);
+
+        for _ in 0..5 {
+            ctrl.update(Msg::Undo);
+        }
+        assert_eq!(ctrl.model.buffer, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_incsearch() {
+        let mut ctrl = headless(vec![0xaa, 0xbb, 0, 0xaa, 0xcc, 0xaa, 0xbb]);
+        ctrl.update(Msg::Move(Direction::Offset(1)));
+        ctrl.execute("set incsearch on");
+
+        ctrl.transition(Key::Char('/'));
+        let typed = |ctrl: &mut Controller, key| {
+            ctrl.transition(key);
+            ctrl.model.get_index()
+        };
+        assert_eq!(typed(&mut ctrl, Key::Char('a')), 1);
+        assert_eq!(typed(&mut ctrl, Key::Char('a')), 3);
+        assert_eq!(typed(&mut ctrl, Key::Char('b')), 3);
+        assert_eq!(typed(&mut ctrl, Key::Char('b')), 5);
+        // No match returns to where the search started
+        assert_eq!(typed(&mut ctrl, Key::Char('f')), 5);
+        assert_eq!(typed(&mut ctrl, Key::Char('f')), 1);
+        assert_eq!(typed(&mut ctrl, Key::Backspace), 5);
+
+        // Cancelling returns as well...
+        assert_eq!(typed(&mut ctrl, Key::Esc), 1);
+
+        // ...while Enter searches from the start, which the jump list remembers
+        for key in "/aa\n".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        assert_eq!(ctrl.model.get_index(), 3);
+        ctrl.transition(Key::Ctrl('o'));
+        assert_eq!(ctrl.model.get_index(), 1);
+    }
+
+    #[test]
+    fn test_search_ignorecase() {
+        let mut ctrl = headless(b"Ab ab AB".to_vec());
+        ctrl.update(Msg::Search(vec![Some(b'a'), Some(b'b')]));
+        assert_eq!(ctrl.model.get_index(), 3);
+        assert!(ctrl.view.status_view.body.contains("[1/1]"));
+
+        ctrl.execute("set ignorecase on");
+        ctrl.update(Msg::SearchNext(Movement::Right));
+        assert_eq!(ctrl.model.get_index(), 6);
+        assert!(ctrl.view.status_view.body.contains("[3/3]"));
+    }
+
+    #[test]
+    fn test_search_wildcard() {
+        let mut ctrl = headless(vec![0xde, 0xad, 0, 0xde, 0xbe, 0xef]);
+        ctrl.execute("set incsearch on");
+
+        ctrl.transition(Key::Char('/'));
+        for key in "de ?".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        // The lone `?` is left out while typing
+        assert_eq!(ctrl.model.get_index(), 3);
+        for key in "? ef\n".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        assert_eq!(ctrl.model.get_index(), 3);
+        assert_eq!(ctrl.view.status_view.body, "/de??ef [1/1]");
+    }
+
+    #[test]
+    fn test_grep() {
+        let mut ctrl = headless(b"id=12 x=7 id=345".to_vec());
+        ctrl.execute("grep id=[0-9]+");
+        assert_eq!(ctrl.model.get_index(), 10);
+        assert_eq!(ctrl.view.status_view.body, ":grep id=[0-9]+ [2/2]");
+
+        ctrl.transition(Key::Char('n'));
+        assert_eq!(ctrl.model.get_index(), 0);
+        assert!(ctrl
+            .view
+            .status_view
+            .body
+            .contains("continuing at TOP [1/2]"));
+        ctrl.transition(Key::Char('N'));
+        assert_eq!(ctrl.model.get_index(), 10);
+
+        // Bytes beyond ASCII are matched on their own
+        ctrl.model.buffer = vec![0, 0xff, 0x80, 0].into();
+        ctrl.execute("grep \\xff.");
+        assert_eq!(ctrl.model.get_index(), 1);
+
+        // A hex search takes over `n` again
+        ctrl.update(Msg::Search(vec![Some(0)]));
+        assert_eq!(ctrl.model.get_index(), 3);
+        assert_eq!(ctrl.view.status_view.body, "/00 [2/2]");
+
+        ctrl.execute("grep a(");
+        assert_eq!(ctrl.view.status_view.body, "invalid regex (unclosed group)");
+    }
+
+    #[test]
+    fn test_notes() {
+        let mut ctrl = headless(vec![0; 32]);
+        ctrl.update(Msg::Move(Direction::Offset(4)));
+        ctrl.execute("note header length, big endian");
+        ctrl.update(Msg::Redraw);
+        assert_eq!(
+            ctrl.view.status_view.note.as_deref(),
+            Some("header length, big endian")
+        );
+
+        ctrl.update(Msg::Move(Direction::Right));
+        ctrl.update(Msg::Redraw);
+        assert_eq!(ctrl.view.status_view.note, None);
+
+        // Deleting before the note moves it along
+        ctrl.update(Msg::Move(Direction::Offset(0)));
+        ctrl.update(Msg::Delete(Some(Movement::Right)));
+        ctrl.update(Msg::Move(Direction::Offset(3)));
+        ctrl.update(Msg::Redraw);
+        assert!(ctrl.view.status_view.note.is_some());
+
+        ctrl.execute("note");
+        assert!(ctrl.model.notes.is_empty());
+    }
+
+    #[test]
+    fn test_showcmd() {
+        let mut ctrl = headless(vec![0; 32]);
+        ctrl.execute("set showcmd on");
+
+        let pending = |ctrl: &mut Controller, key| {
+            ctrl.transition(key);
+            ctrl.update(Msg::Redraw);
+            ctrl.view.status_view.pending.clone().unwrap()
+        };
+        assert_eq!(pending(&mut ctrl, Key::Char('1')), "1");
+        assert_eq!(pending(&mut ctrl, Key::Char('2')), "12");
+        assert_eq!(pending(&mut ctrl, Key::Char('G')), "");
+        assert_eq!(pending(&mut ctrl, Key::Char('d')), "d");
+        assert_eq!(pending(&mut ctrl, Key::Char('w')), "");
+        assert_eq!(pending(&mut ctrl, Key::Char('g')), "g");
+        assert_eq!(pending(&mut ctrl, Key::Esc), "");
+        assert_eq!(pending(&mut ctrl, Key::Char('f')), "f");
+        assert_eq!(pending(&mut ctrl, Key::Char('0')), "f0");
+        assert_eq!(pending(&mut ctrl, Key::Char('0')), "");
+
+        ctrl.execute("set showcmd off");
+        ctrl.update(Msg::Redraw);
+        assert_eq!(ctrl.view.status_view.pending, None);
+    }
+
+    #[test]
+    fn test_empty_insert() {
+        let mut ctrl = headless(vec![]);
+        ctrl.update(Msg::ToInsert(None));
+        ctrl.update(Msg::Move(Direction::Right));
+        assert!(matches!(ctrl.model.caret, Caret::Index(index) if index.value() == 0));
+        ctrl.update(Msg::ToNormal);
+        assert!(matches!(ctrl.model.caret, Caret::Offset(index) if index.value() == 0));
+        ctrl.update(Msg::Redraw);
+
+        // Appending to an empty buffer inserts at its start, like inserting does
+        for keys in ["aab", "iab", "Aab", "gIab"] {
+            let mut ctrl = headless(vec![]);
+            for key in keys.chars() {
+                ctrl.transition(Key::Char(key));
+            }
+            assert_eq!(ctrl.model.buffer, vec![0xab], "{}", keys);
+            ctrl.transition(Key::Esc);
+            assert!(matches!(ctrl.model.caret, Caret::Offset(index) if index.value() == 0));
+
+            // Deleting the only byte leaves an empty buffer to insert into again
+            ctrl.transition(Key::Char('x'));
+            assert!(ctrl.model.is_empty());
+            for key in "acd".chars() {
+                ctrl.transition(Key::Char(key));
+            }
+            ctrl.transition(Key::Esc);
+            assert_eq!(ctrl.model.buffer, vec![0xcd], "{}", keys);
+        }
+    }
+
+    #[test]
+    fn test_help() {
+        let mut ctrl = headless(vec![0; 16]);
+        ctrl.transition(Key::Char('?'));
+        assert!(matches!(ctrl.state, VimState::Help));
+
+        // Scrolling moves the help, not the caret
+        ctrl.transition(Key::Char('j'));
+        ctrl.transition(Key::Ctrl('d'));
+        assert!(matches!(ctrl.state, VimState::Help));
+        assert_eq!(ctrl.model.get_index(), 0);
+
+        ctrl.transition(Key::Char('q'));
+        assert!(matches!(ctrl.state, VimState::Normal));
+
+        ctrl.execute("help");
+        assert!(matches!(ctrl.state, VimState::Help));
+        ctrl.transition(Key::Esc);
+        assert!(matches!(ctrl.state, VimState::Normal));
+    }
+
+    #[test]
+    fn test_noundo() {
+        let mut ctrl = headless(vec![0]);
+        ctrl.snapshot();
+        ctrl.update(Msg::ToInsert(None));
+        ctrl.update(Msg::Byte(1));
+
+        ctrl.execute("set noundo");
+        ctrl.update(Msg::Byte(2));
+        ctrl.update(Msg::Undo);
+        assert_eq!(
+            ctrl.view.status_view.body,
+            "undo is disabled (enable with :set undo on)"
+        );
+        assert_eq!(ctrl.model.buffer, vec![1, 2, 0]);
+
+        // The history starts over, nothing from before comes back
+        ctrl.execute("set undo on");
+        ctrl.update(Msg::Undo);
+        assert_eq!(ctrl.view.status_view.body, "Nothing to undo");
+        ctrl.update(Msg::Byte(3));
+        ctrl.update(Msg::Undo);
+        assert_eq!(ctrl.model.buffer, vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn test_select_range() {
+        let mut ctrl = headless(vec![0; 0x100]);
+
+        for key in ":select 0x10 0x2f\n".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        assert!(matches!(ctrl.state, VimState::Visual));
+        assert_eq!(ctrl.selection(), Some((0x10, 0x2f)));
+        assert_eq!(ctrl.model.get_index(), 0x2f);
+
+        // The anchor stays where it was set, and both ends are clamped
+        ctrl.execute("select 0x1000 0x80");
+        assert_eq!(ctrl.selection(), Some((0x80, 0xff)));
+        assert_eq!(ctrl.model.get_index(), 0x80);
+
+        let mut ctrl = headless(vec![]);
+        ctrl.execute("select 0 1");
+        assert_eq!(ctrl.view.status_view.body, "nothing to select");
+        assert!(ctrl.selection().is_none());
+    }
+
+    #[test]
+    fn test_select_word() {
+        let mut ctrl = headless(vec![0, 1, 2, 3, 0, 0, 4, 5]);
+
+        ctrl.update(Msg::Move(Direction::Offset(2)));
+        ctrl.transition(Key::Char('v'));
+        ctrl.transition(Key::Char('i'));
+        ctrl.transition(Key::Char('w'));
+        assert!(matches!(ctrl.state, VimState::Visual));
+        assert_eq!(ctrl.selection(), Some((1, 3)));
+        assert_eq!(ctrl.model.get_index(), 3);
+
+        // Selecting again from the gap takes in the run of zeros, then the next word
+        ctrl.update(Msg::Move(Direction::Right));
+        ctrl.update(Msg::SelectWord);
+        assert_eq!(ctrl.selection(), Some((1, 5)));
+        ctrl.update(Msg::Move(Direction::Right));
+        ctrl.update(Msg::SelectWord);
+        assert_eq!(ctrl.selection(), Some((1, 7)));
+
+        // Backwards selections grow backwards
+        ctrl.update(Msg::ToNormal);
+        ctrl.update(Msg::Move(Direction::Offset(5)));
+        ctrl.update(Msg::ToVisual);
+        ctrl.update(Msg::Move(Direction::Left));
+        ctrl.update(Msg::Move(Direction::Left));
+        ctrl.update(Msg::SelectWord);
+        assert_eq!(ctrl.selection(), Some((1, 5)));
+        assert_eq!(ctrl.model.get_index(), 1);
+    }
+
+    #[test]
+    fn test_set_many() {
+        let mut ctrl = headless(vec![0; 64]);
+
+        ctrl.execute("set wrapscan off bogus record 4");
+        assert!(!ctrl.wrapscan);
+        assert_eq!(ctrl.view.hex_view.get_record(), Some(4));
+        assert_eq!(ctrl.view.status_view.body, "no such option");
+    }
+
+    #[test]
+    fn test_quit() {
+        // A new, empty buffer has nothing to lose...
+        let mut ctrl = headless(vec![]);
+        ctrl.model.path = "/nonexistent/new.bin".into();
+        assert!(!ctrl.update(Msg::Quit));
+
+        // ...unlike one with content
+        ctrl.model.buffer = vec![0x00].into();
+        assert!(ctrl.update(Msg::Quit));
+        assert!(ctrl.view.status_view.body.contains("Save changes?"));
+        assert!(matches!(ctrl.state, VimState::Confirm(PendingAction::Quit)));
+
+        ctrl.state = VimState::Normal;
+        ctrl.model.path = "".into();
+        assert!(ctrl.update(Msg::Quit));
+        assert!(ctrl.view.status_view.body.contains(":saveas <file>"));
+        assert!(matches!(ctrl.state, VimState::Normal));
+
+        assert!(!ctrl.update(Msg::QuitWithoutSaving));
+    }
+
+    #[test]
+    fn test_confirm_quit() {
+        let mut ctrl = headless(vec![0x00]);
+        ctrl.model.path = "/nonexistent/dir/file.bin".into();
+
+        for key in ":q\n".chars() {
+            assert!(ctrl.transition(Key::Char(key)));
+        }
+        assert!(matches!(ctrl.state, VimState::Confirm(_)));
+
+        // Other keys are rejected until one of the choices is made
+        assert!(ctrl.transition(Key::Char('x')));
+        assert!(matches!(ctrl.state, VimState::Confirm(_)));
+        assert!(ctrl.transition(Key::Char('c')));
+        assert!(matches!(ctrl.state, VimState::Normal));
+
+        // Saving fails here, which keeps xim running
+        for key in ":q\ny".chars() {
+            assert!(ctrl.transition(Key::Char(key)));
+        }
+        assert!(matches!(ctrl.state, VimState::Normal));
+
+        for key in ":q\n".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        assert!(!ctrl.transition(Key::Char('n')));
+    }
+
+    #[test]
+    fn test_saveas_existing() {
+        use std::fs::{read, write};
+
+        let file = TempFile::new("saveas");
+        write(&file, [0xaa]).unwrap();
+        let other = file.path().to_owned();
+
+        let mut ctrl = headless(vec![0x00]);
+        ctrl.update(Msg::SaveAs(other.clone(), false));
+        assert_eq!(read(&other).unwrap(), vec![0xaa]);
+        assert!(ctrl.view.status_view.body.contains("exists"));
+
+        ctrl.update(Msg::SaveAs(other.clone(), true));
+        assert_eq!(read(&other).unwrap(), vec![0x00]);
+        assert!(ctrl
+            .view
+            .status_view
+            .body
+            .starts_with(&format!("\"{}\" 1 bytes written in ", other)));
+    }
+
+    #[test]
+    fn test_nibble_mode() {
+        let mut ctrl = headless(vec![0x00, 0x11, 0x22]);
+
+        ctrl.update(Msg::Set(Setting::Nibble(true)));
+        for key in "Ra".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+
+        // Only the high nibble is set, the caret stays...
+        assert_eq!(ctrl.model.buffer, vec![0xa0, 0x11, 0x22]);
+        assert_eq!(ctrl.model.get_index(), 0);
+
+        // ...until the low nibble completes the byte.
+        for key in "bc".chars() {
+            ctrl.transition(Key::Char(key));
+        }
+        assert_eq!(ctrl.model.buffer, vec![0xab, 0xc1, 0x22]);
+        assert_eq!(ctrl.model.get_index(), 1);
+
+        ctrl.transition(Key::Esc);
+        assert_eq!(ctrl.model.buffer, vec![0xab, 0xc1, 0x22]);
+    }
+
+    #[test]
+    fn test_source() {
+        let file = TempFile::new("source");
+        fs::write(&file, "\" jump and replace\n:2\n\nnosuchcommand\n0\n").unwrap();
+        let script = file.path();
+
+        let mut ctrl = headless(vec![0; 4]);
+        let error = ctrl.source(script, false).unwrap_err();
+        assert!(error.ends_with(":4: no such command (nosuchcommand)"));
+        assert_eq!(ctrl.model.get_index(), 2);
+
+        let mut ctrl = headless(vec![0; 4]);
+        assert_eq!(ctrl.source(script, true), Ok(true));
+        assert_eq!(ctrl.model.get_index(), 0);
+
+        // A command failing when run stops the script as well
+        let unwritable = env::temp_dir().join("nonexistent").join("file");
+        let unwritable = unwritable.to_str().unwrap();
+        fs::write(&file, "w\n2\n").unwrap();
+        let mut ctrl = headless(vec![0; 4]);
+        ctrl.model.path = unwritable.into();
+        let error = ctrl.source(script, false).unwrap_err();
+        assert!(error.starts_with(&format!(
+            "{}:1: could not save \"{}\": ",
+            script, unwritable
+        )));
+        assert_eq!(ctrl.model.get_index(), 0);
+
+        let mut ctrl = headless(vec![0; 4]);
+        ctrl.model.path = unwritable.into();
+        assert_eq!(ctrl.source(script, true), Ok(true));
+        assert_eq!(ctrl.model.get_index(), 2);
     }
 
     #[quickcheck]
@@ -1,7 +1,12 @@
+// Like Vim's default for `undolevels`
+const DEFAULT_LIMIT: usize = 1000;
+
 #[derive(Debug)]
 pub struct History<T> {
     done: Vec<T>,
     recall: Vec<T>,
+    // Undo steps kept before the current state, older ones are dropped
+    limit: usize,
 }
 
 impl<T: Clone> History<T> {
@@ -9,9 +14,25 @@ impl<T: Clone> History<T> {
         History {
             done: Vec::new(),
             recall: Vec::new(),
+            limit: DEFAULT_LIMIT,
         }
     }
 
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.forget();
+    }
+
+    pub fn clear(&mut self) {
+        self.done = Vec::new();
+        self.recall = Vec::new();
+    }
+
+    fn forget(&mut self) {
+        let excess = self.done.len().saturating_sub(self.limit.saturating_add(1));
+        self.done.drain(..excess);
+    }
+
     pub fn init(&mut self, initial: &T) {
         self.snapshot(initial);
     }
@@ -19,6 +40,7 @@ impl<T: Clone> History<T> {
     pub fn snapshot(&mut self, current: &T) {
         self.done.push(current.clone());
         self.recall.clear();
+        self.forget();
     }
 
     pub fn undo(&mut self) -> Option<T> {
@@ -77,4 +99,24 @@ mod tests {
         assert_eq!(hist.redo(), Some(3));
         assert_eq!(hist.checkout(), Some(3));
     }
+
+    #[test]
+    fn limit() {
+        let mut hist: History<u8> = History::new();
+        hist.set_limit(2);
+        hist.init(&0);
+
+        for i in 1..=4 {
+            hist.snapshot(&i);
+        }
+        assert_eq!(hist.undo(), Some(3));
+        assert_eq!(hist.undo(), Some(2));
+        assert_eq!(hist.undo(), None);
+
+        // Lowering the limit drops the oldest steps right away
+        assert_eq!(hist.redo(), Some(3));
+        hist.set_limit(0);
+        assert_eq!(hist.undo(), None);
+        assert_eq!(hist.checkout(), Some(3));
+    }
 }
@@ -2,8 +2,10 @@ use std::{
     cell::RefCell,
     cmp::min,
     error::Error,
-    io::{stdin, stdout, Stdout, Write},
+    fs::{read_to_string, OpenOptions},
+    io::{sink, stdin, stdout, Result as IoResult, Write},
     ops::{Add, AddAssign, Drop, Rem, RemAssign, Sub, SubAssign},
+    path::Path,
     rc::Rc,
     sync::mpsc::sync_channel,
     thread,
@@ -11,12 +13,13 @@ use std::{
 
 use chan_signal::{notify, Signal};
 use termion::{
-    event::Key,
-    input::TermRead,
+    event::{Event as TermEvent, Key, MouseButton, MouseEvent},
+    input::{MouseTerminal, TermRead},
     raw::{IntoRawMode, RawTerminal},
-    screen::AlternateScreen,
+    screen::{AlternateScreen, ToAlternateScreen, ToMainScreen},
 };
 
+mod buffer;
 mod controller;
 mod history;
 mod model;
@@ -24,43 +27,86 @@ mod utils;
 mod view;
 mod vim;
 
-use controller::{Controller, Msg};
+use controller::{Controller, Msg, Setting, Suspend};
 use model::Model;
+use utils::parse_key;
 use view::View;
 
-pub type RawStdout = Rc<RefCell<AlternateScreen<RawTerminal<Stdout>>>>;
+// Like termion's `MouseTerminal`, which doesn't expose them
+const ENTER_MOUSE_SEQUENCE: &str = "\x1b[?1000h\x1b[?1002h\x1b[?1015h\x1b[?1006h";
+const EXIT_MOUSE_SEQUENCE: &str = "\x1b[?1006l\x1b[?1015l\x1b[?1002l\x1b[?1000l";
+
+// Any writer can be drawn to. The app uses the raw terminal, tests may render into a `Vec<u8>`.
+pub type RawStdout = Rc<RefCell<dyn Write>>;
 
 enum Event {
     Key(Key),
     Resize((u16, u16)),
+    // A left click at a terminal cell
+    Click((u16, u16)),
     Kill,
 }
 
 pub struct Args {
     pub file: String,
+    pub config: Option<String>,
+    pub source: Option<String>,
+    pub force: bool,
+    pub batch: bool,
+    pub log: Option<String>,
+    pub replay: Option<String>,
+    // Report mouse events, which spares the terminal's own text selection if off
+    pub mouse: bool,
+    // Of the character pane, like `:set encoding`
+    pub encoding: Option<String>,
 }
 
 pub struct App {
     args: Args,
     stdout: RawStdout,
+    // Hands the terminal to external programs, none without a terminal
+    suspend: Option<Suspend>,
 }
 
 impl App {
     pub fn new(args: Args) -> App {
+        // Batch and replay mode never touch the terminal
+        let (stdout, suspend): (RawStdout, _) = if args.batch || args.replay.is_some() {
+            (Rc::new(RefCell::new(sink())), None)
+        } else if args.mouse {
+            let terminal = AlternateScreen::from(MouseTerminal::from(stdout()))
+                .into_raw_mode()
+                .unwrap();
+            let (stdout, suspend) = share_terminal(terminal, true);
+            (stdout, Some(suspend))
+        } else {
+            let terminal = AlternateScreen::from(stdout()).into_raw_mode().unwrap();
+            let (stdout, suspend) = share_terminal(terminal, false);
+            (stdout, Some(suspend))
+        };
+
         App {
             args,
-            stdout: Rc::new(RefCell::new(AlternateScreen::from(
-                stdout().into_raw_mode().unwrap(),
-            ))),
+            stdout,
+            suspend,
         }
     }
 
     pub fn run(mut self) -> Result<(), Box<dyn Error>> {
+        if self.args.batch {
+            return self.run_batch();
+        }
+
+        if let Some(log) = self.args.replay.clone() {
+            return self.run_replay(&log);
+        }
+
         self.setup_terminal()?;
 
-        let events = {
+        let (events, handled) = {
             // Create event channel
             let (send, recv) = sync_channel(0);
+            let (handled_send, handled_recv) = sync_channel(1);
 
             // Listen for window changed and terminate signals
             let signals = notify(&[Signal::WINCH, Signal::TERM]);
@@ -78,24 +124,148 @@ impl App {
                 }
             });
 
-            // Receive keypress events
+            // Receive keypress and mouse events. Each one is handled before reading on, so
+            // external programs started by a key can have stdin to themselves.
             let send_2 = send;
             thread::spawn(move || {
-                for c in stdin().keys() {
-                    send_2.send(Event::Key(c.unwrap())).unwrap();
+                for event in stdin().events() {
+                    let event = match event.unwrap() {
+                        TermEvent::Key(k) => Event::Key(k),
+                        TermEvent::Mouse(MouseEvent::Press(MouseButton::Left, x, y)) => {
+                            Event::Click((x, y))
+                        }
+                        _ => continue,
+                    };
+                    send_2.send(event).unwrap();
+                    if handled_recv.recv().is_err() {
+                        break;
+                    }
                 }
             });
 
-            recv
+            (recv, handled_send)
         };
 
         let mut ctrl = Controller::new(Model::new(), View::new(self.stdout.clone()));
+        self.open_log(&mut ctrl)?;
+        if let Some(suspend) = self.suspend.take() {
+            ctrl.set_suspend(suspend);
+        }
 
         ctrl.update(Msg::Resize(termion::terminal_size()?));
         ctrl.update(Msg::Open(self.args.file.clone()));
+
+        if let Err(error) = self.apply_config(&mut ctrl) {
+            ctrl.update(Msg::Show(error));
+        }
+
+        if let Some(ref script) = self.args.source {
+            match ctrl.source(script, self.args.force) {
+                Ok(true) => {}
+                Ok(false) => return Ok(()),
+                Err(error) => {
+                    ctrl.update(Msg::Show(error));
+                }
+            }
+        }
+
         ctrl.update(Msg::Redraw);
 
         for event in events.iter() {
+            match event {
+                Event::Key(k) => {
+                    if !ctrl.transition(k) {
+                        break;
+                    }
+                    let _ = handled.send(());
+                }
+                Event::Resize(new_size) => {
+                    ctrl.update(Msg::Resize(new_size));
+                }
+                Event::Click((x, y)) => {
+                    ctrl.update(Msg::Click(x, y));
+                    let _ = handled.send(());
+                }
+                Event::Kill => break,
+            }
+
+            ctrl.update(Msg::Redraw);
+        }
+
+        Ok(())
+    }
+
+    // Runs the `set ...` lines of the config file. A missing file is fine.
+    // The flags come after it, so they win over the config.
+    fn apply_config(&self, ctrl: &mut Controller) -> Result<(), String> {
+        match self.args.config {
+            Some(ref config) if Path::new(config).exists() => {
+                ctrl.source(config, false)?;
+            }
+            _ => {}
+        }
+
+        if let Some(ref encoding) = self.args.encoding {
+            let setting = Setting::parse(&format!("encoding {}", encoding))
+                .map_err(|error| format!("--encoding: {}", error))?;
+            ctrl.update(Msg::Set(setting));
+        }
+
+        Ok(())
+    }
+
+    // Appends to an existing log, so several sessions can be traced into one file
+    fn open_log(&self, ctrl: &mut Controller) -> Result<(), Box<dyn Error>> {
+        if let Some(ref log) = self.args.log {
+            let file = OpenOptions::new().create(true).append(true).open(log)?;
+            ctrl.set_log(Box::new(file));
+        }
+        Ok(())
+    }
+
+    // Applies the sourced script without drawing or reading keys, then exits.
+    // The script must save (e.g. with `:w`) for the changes to persist. A command failing to
+    // write ends the batch with an error, unless `--force` is given.
+    fn run_batch(&mut self) -> Result<(), Box<dyn Error>> {
+        let script = self
+            .args
+            .source
+            .clone()
+            .ok_or("--batch requires --source")?;
+
+        let mut model = Model::new();
+        model.open(&self.args.file)?;
+
+        let mut ctrl = Controller::new(model, View::new(self.stdout.clone()));
+        self.open_log(&mut ctrl)?;
+        self.apply_config(&mut ctrl)?;
+        ctrl.source(&script, self.args.force)?;
+
+        // Nothing is drawn, so the last message, like that of `:w`, goes to stderr
+        let status = &ctrl.view.status_view.body;
+        if !status.is_empty() {
+            eprintln!("{}", status);
+        }
+
+        Ok(())
+    }
+
+    // Feeds the keys and resizes recorded with `--log` through a controller drawing into nothing.
+    // Like in the interface, the file is only written by commands like `:w`.
+    fn run_replay(&mut self, log: &str) -> Result<(), Box<dyn Error>> {
+        let events: Vec<_> = read_to_string(log)?
+            .lines()
+            .filter_map(parse_log_line)
+            .collect();
+
+        let mut ctrl = Controller::new(Model::new(), View::new(self.stdout.clone()));
+        self.open_log(&mut ctrl)?;
+
+        ctrl.update(Msg::Resize((80, 24)));
+        ctrl.update(Msg::Open(self.args.file.clone()));
+        self.apply_config(&mut ctrl)?;
+
+        for event in events {
             match event {
                 Event::Key(k) => {
                     if !ctrl.transition(k) {
@@ -105,6 +275,9 @@ impl App {
                 Event::Resize(new_size) => {
                     ctrl.update(Msg::Resize(new_size));
                 }
+                Event::Click((x, y)) => {
+                    ctrl.update(Msg::Click(x, y));
+                }
                 Event::Kill => break,
             }
 
@@ -133,12 +306,70 @@ impl App {
 
 impl Drop for App {
     fn drop(&mut self) {
+        if self.args.batch || self.args.replay.is_some() {
+            return;
+        }
+
         if let Err(error) = self.teardown_terminal() {
             eprintln!("{}", error);
         }
     }
 }
 
+// Shares the raw terminal with the view, along with a way to leave it to an external program
+// and to take it back: alternate screen, mouse reporting, the cursor and raw mode are undone.
+fn share_terminal<W: Write + 'static>(
+    terminal: RawTerminal<W>,
+    mouse: bool,
+) -> (RawStdout, Suspend) {
+    let terminal = Rc::new(RefCell::new(terminal));
+    let handle = terminal.clone();
+
+    let suspend = move |suspend: bool| -> IoResult<()> {
+        let mut terminal = handle.borrow_mut();
+        if suspend {
+            if mouse {
+                write!(terminal, "{}", EXIT_MOUSE_SEQUENCE)?;
+            }
+            write!(terminal, "{}{}", ToMainScreen, termion::cursor::Show)?;
+            terminal.flush()?;
+            terminal.suspend_raw_mode()
+        } else {
+            terminal.activate_raw_mode()?;
+            write!(terminal, "{}{}", ToAlternateScreen, termion::cursor::Hide)?;
+            if mouse {
+                write!(terminal, "{}", ENTER_MOUSE_SEQUENCE)?;
+            }
+            terminal.flush()
+        }
+    };
+
+    (terminal, Box::new(suspend))
+}
+
+// Reads the keys and resizes from a line of the log, e.g. `1700000000.123 key Char('x') in Normal`
+fn parse_log_line(line: &str) -> Option<Event> {
+    let (_, entry) = line.split_once(' ')?;
+
+    if let Some(rest) = entry.strip_prefix("key ") {
+        // Keys with a character may contain spaces themselves, like `Char(' ')`
+        let end =
+            if rest.starts_with("Char(") || rest.starts_with("Alt(") || rest.starts_with("Ctrl(") {
+                rest.find("') in ")? + 2
+            } else {
+                rest.find(' ')?
+            };
+        parse_key(&rest[..end]).map(Event::Key)
+    } else if let Some(cell) = entry.strip_prefix("msg Click(") {
+        let (x, y) = cell.strip_suffix(')')?.split_once(", ")?;
+        Some(Event::Click((x.parse().ok()?, y.parse().ok()?)))
+    } else {
+        let size = entry.strip_prefix("msg Resize((")?.strip_suffix("))")?;
+        let (w, h) = size.split_once(", ")?;
+        Some(Event::Resize((w.parse().ok()?, h.parse().ok()?)))
+    }
+}
+
 pub trait Ascii {
     fn to_printable(self) -> char;
 }
@@ -175,6 +406,15 @@ impl UsizeMax {
         self.max
     }
 
+    pub fn value(&self) -> usize {
+        self.value
+    }
+
+    // How far the value may still grow
+    pub fn remaining(&self) -> usize {
+        self.max - self.value
+    }
+
     fn adjust(&mut self) {
         self.value = min(self.value, self.max);
     }
@@ -203,7 +443,7 @@ impl AddAssign<usize> for UsizeMax {
     }
 }
 
-impl<'a> AddAssign<usize> for &'a mut UsizeMax {
+impl AddAssign<usize> for &mut UsizeMax {
     fn add_assign(&mut self, other: usize) {
         self.value = self.value.saturating_add(other);
         self.adjust();
@@ -227,34 +467,33 @@ impl SubAssign<usize> for UsizeMax {
     }
 }
 
-impl<'a> SubAssign<usize> for &'a mut UsizeMax {
+impl SubAssign<usize> for &mut UsizeMax {
     fn sub_assign(&mut self, other: usize) {
         self.value = self.value.saturating_sub(other);
         self.adjust();
     }
 }
 
+// A remainder by zero leaves the value unchanged, instead of panicking
 impl Rem<usize> for UsizeMax {
     type Output = UsizeMax;
 
     fn rem(mut self, other: usize) -> UsizeMax {
-        self.value %= other;
-        self.adjust();
+        self %= other;
         self
     }
 }
 
 impl RemAssign<usize> for UsizeMax {
     fn rem_assign(&mut self, other: usize) {
-        self.value = self.value % other;
+        self.value = self.value.checked_rem(other).unwrap_or(self.value);
         self.adjust();
     }
 }
 
-impl<'a> RemAssign<usize> for &'a mut UsizeMax {
+impl RemAssign<usize> for &mut UsizeMax {
     fn rem_assign(&mut self, other: usize) {
-        self.value = self.value % other;
-        self.adjust();
+        **self %= other;
     }
 }
 
@@ -272,6 +511,40 @@ impl<'a> From<&'a mut UsizeMax> for usize {
     }
 }
 
+// A file in the temp directory, named after the test and this process. It is removed on drop,
+// so a failing assertion doesn't leave it behind.
+#[cfg(test)]
+struct TempFile(String);
+
+#[cfg(test)]
+impl TempFile {
+    fn new(name: &str) -> TempFile {
+        let path = std::env::temp_dir().join(format!("xim-test-{}-{}", name, std::process::id()));
+        let file = TempFile(path.to_str().unwrap().into());
+        // Left over from an aborted run
+        let _ = std::fs::remove_file(&file);
+        file
+    }
+
+    fn path(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<Path> for TempFile {
+    fn as_ref(&self) -> &Path {
+        Path::new(&self.0)
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use quickcheck_macros::quickcheck;
@@ -292,4 +565,123 @@ mod tests {
 
         usize::from(value) <= max
     }
+
+    #[test]
+    fn test_parse_log_line() {
+        let parse = |line| match parse_log_line(line) {
+            Some(Event::Key(key)) => format!("{:?}", key),
+            Some(Event::Resize(size)) | Some(Event::Click(size)) => format!("{:?}", size),
+            Some(Event::Kill) => "kill".into(),
+            None => "none".into(),
+        };
+
+        assert_eq!(parse("1.000 key Char(' ') in Normal"), "Char(' ')");
+        assert_eq!(
+            parse("1.000 key Char('\\'') in Command(\"') in \")"),
+            "Char('\\'')"
+        );
+        assert_eq!(parse("1.000 key Left in Command(\"') in \")"), "Left");
+        assert_eq!(parse("1.000 msg Resize((80, 24))"), "(80, 24)");
+        assert_eq!(parse("1.000 msg Click(7, 3)"), "(7, 3)");
+        assert_eq!(parse("1.000 msg Move(Right)"), "none");
+        assert_eq!(parse("1.000 state Normal"), "none");
+    }
+
+    #[test]
+    fn test_replay() {
+        use std::fs;
+
+        let file = TempFile::new("replay");
+        let log = TempFile::new("replay-log");
+        fs::write(&file, [0x00]).unwrap();
+
+        let keys = ['i', 'a', 'b', '\u{1b}', ':', 'w', 'q', '\n', 'x'];
+        let entries: Vec<_> = keys
+            .iter()
+            .map(|&c| {
+                let key = if c == '\u{1b}' {
+                    Key::Esc
+                } else {
+                    Key::Char(c)
+                };
+                format!("0.000 key {:?} in Normal\n0.000 state Normal\n", key)
+            })
+            .collect();
+        fs::write(&log, entries.concat()).unwrap();
+
+        App::new(Args {
+            file: file.path().into(),
+            config: None,
+            source: None,
+            force: false,
+            batch: false,
+            log: None,
+            replay: Some(log.path().into()),
+            mouse: true,
+            encoding: None,
+        })
+        .run()
+        .unwrap();
+
+        // The trailing `x` comes after quitting
+        assert_eq!(fs::read(&file).unwrap(), vec![0xab, 0x00]);
+    }
+
+    #[test]
+    fn test_batch_failure() {
+        use std::fs;
+
+        let file = TempFile::new("batch");
+        let script = TempFile::new("batch-script");
+        fs::write(&file, [0x00]).unwrap();
+        let unwritable = std::env::temp_dir().join("nonexistent").join("file");
+        fs::write(&script, format!("saveas {}\n", unwritable.display())).unwrap();
+
+        let error = App::new(Args {
+            file: file.path().into(),
+            config: None,
+            source: Some(script.path().into()),
+            force: false,
+            batch: true,
+            log: None,
+            replay: None,
+            mouse: true,
+            encoding: None,
+        })
+        .run()
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with(&format!("{}:1: could not save", script.path())));
+    }
+
+    #[quickcheck]
+    fn test_usizemax_rem(value: usize, max: usize, other: usize) -> bool {
+        let value = UsizeMax::new(value, max);
+        let mut assigned = value;
+        assigned %= other;
+
+        let expected = if other == 0 {
+            value.value()
+        } else {
+            value.value() % other
+        };
+        (value % other).value() == expected && assigned.value() == expected && expected <= max
+    }
+
+    #[test]
+    fn test_usizemax_rem_zero() {
+        let mut value = UsizeMax::new(7, 10);
+        assert_eq!((value % 0).value(), 7);
+        let mut borrowed = &mut value;
+        borrowed %= 0;
+        assert_eq!(value.value(), 7);
+        assert_eq!((value % 4).value(), 3);
+    }
+
+    #[quickcheck]
+    fn test_usizemax_remaining(value: usize, max: usize) -> bool {
+        let value = UsizeMax::new(value, max);
+        value.value() + value.remaining() == max
+    }
 }
@@ -1,3 +1,5 @@
+use std::env;
+
 use docopt::Docopt;
 use serde_derive::Deserialize;
 use xim::{App, Args};
@@ -6,13 +8,21 @@ const USAGE: &str = "
 Xim
 
 Usage:
-  xim <file>
+  xim [options] <file>
   xim (-h | --help)
   xim --version
 
 Options:
-  -h --help     Show this screen.
-  --version     Show version.
+  -h --help          Show this screen.
+  --version          Show version.
+  --config <file>    Read settings from <file> [default: ~/.config/xim/config].
+  --source <script>  Run the commands in <script> (one per line) after opening.
+  --force            Continue sourcing after a failing command.
+  --batch            Exit after sourcing, without starting the interface.
+  --log <file>       Append the processed messages and key transitions to <file>.
+  --replay <log>     Replay the keys recorded with --log, without the interface.
+  --no-mouse         Leave the mouse to the terminal, e.g. for its text selection.
+  --encoding <name>  Decode the character pane as ascii, latin1, cp437 or utf8.
 ";
 
 // Get version from Cargo.toml
@@ -21,6 +31,14 @@ const VERSION: &str = env!("CARGO_PKG_VERSION");
 #[derive(Deserialize)]
 struct DocoptArgs {
     arg_file: String,
+    flag_config: Option<String>,
+    flag_source: Option<String>,
+    flag_force: bool,
+    flag_batch: bool,
+    flag_log: Option<String>,
+    flag_replay: Option<String>,
+    flag_no_mouse: bool,
+    flag_encoding: Option<String>,
 }
 
 // Translation of `DocoptArgs` to `xim::Args`
@@ -28,10 +46,26 @@ impl From<DocoptArgs> for Args {
     fn from(args: DocoptArgs) -> Args {
         Args {
             file: args.arg_file,
+            config: args.flag_config.map(expand_home),
+            source: args.flag_source,
+            force: args.flag_force,
+            batch: args.flag_batch,
+            log: args.flag_log,
+            replay: args.flag_replay,
+            mouse: !args.flag_no_mouse,
+            encoding: args.flag_encoding,
         }
     }
 }
 
+// Resolve a leading `~/`, the shell doesn't do that for the docopt default
+fn expand_home(path: String) -> String {
+    match (path.strip_prefix("~/"), env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home, rest),
+        _ => path,
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Parse arguments
     let args: DocoptArgs = Docopt::new(USAGE)
@@ -1,10 +1,21 @@
 use std::{
-    fs::{File, OpenOptions},
+    borrow::Cow,
+    cmp::min,
+    collections::HashMap,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::ErrorKind,
     io::{Read, Result as IoResult, Write},
     mem::swap,
+    ops::RangeBounds,
+    path::{Path, PathBuf},
+    process,
 };
 
-use crate::{history::History, UsizeMax};
+use crate::{buffer::Buffer, history::History, UsizeMax};
+
+// Files are read in chunks of this size, to report the progress in between
+pub const READ_CHUNK: usize = 4 * 1024 * 1024;
 
 #[derive(Clone, Debug)]
 pub enum Caret {
@@ -14,13 +25,46 @@ pub enum Caret {
     Visual(UsizeMax, UsizeMax),
 }
 
+#[derive(Debug, PartialEq)]
+pub enum EditError {
+    // The range to replace reaches beyond the buffer
+    OutOfBounds {
+        start: usize,
+        end: usize,
+        len: usize,
+    },
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EditError::OutOfBounds { start, end, len } => write!(
+                f,
+                "no data to edit at {}..{}, the buffer has {} bytes",
+                start, end, len
+            ),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Model {
     pub path: String,
     pub caret: Caret,
-    pub buffer: Vec<u8>,
-    pub term_size: (u16, u16),
-    history: History<(Vec<u8>, Caret)>,
+    pub buffer: Buffer,
+    // Overwrite files in place instead of replacing them atomically
+    pub backupcopy: bool,
+    // Lock the opened file against other xim instances
+    pub swapfile: bool,
+    // The lock file this instance created, removed again on drop
+    lock: Option<PathBuf>,
+    // Without it no snapshots are taken, which spares their memory for huge files
+    undo: bool,
+    history: History<(Buffer, Caret, usize)>,
+    // Notes on single bytes, set with `:note`. They are not saved and not part of the history.
+    pub notes: HashMap<usize, String>,
+    // Lets the Normal and Replace mode caret rest one past the last byte
+    pub virtualedit: bool,
 }
 
 impl Model {
@@ -28,61 +72,201 @@ impl Model {
         Model {
             path: "".into(),
             caret: Caret::Offset(UsizeMax::new(0, 0)),
-            buffer: vec![],
-            term_size: (16, 16),
+            buffer: Buffer::new(),
+            backupcopy: false,
+            swapfile: true,
+            lock: None,
+            undo: true,
             history: History::new(),
+            notes: HashMap::new(),
+            virtualedit: false,
         }
     }
 
     pub fn open(&mut self, path: &str) -> IoResult<()> {
+        self.open_with_progress(path, |_, _| {})
+    }
+
+    // Calls `progress` with the bytes read so far and the file size after every chunk
+    pub fn open_with_progress<F>(&mut self, path: &str, mut progress: F) -> IoResult<()>
+    where
+        F: FnMut(usize, usize),
+    {
         self.path = path.into();
 
         let buffer = {
-            let mut file = OpenOptions::new()
+            let file = OpenOptions::new()
                 .read(true)
                 .write(true)
                 .create(true)
+                .truncate(false)
                 .open(path)?;
 
-            let mut buffer = Vec::new();
-            file.read_to_end(&mut buffer)?;
+            let size = file.metadata()?.len() as usize;
+            let mut buffer = Vec::with_capacity(size);
+
+            while (&file).take(READ_CHUNK as u64).read_to_end(&mut buffer)? > 0 {
+                progress(buffer.len(), size);
+            }
             buffer
         };
 
-        self.buffer = buffer;
-        self.caret = Caret::Offset(UsizeMax::new(0, self.buffer.len().saturating_sub(1)));
+        self.buffer = buffer.into();
+        self.caret = Caret::Offset(UsizeMax::new(0, self.last_offset()));
+        self.notes.clear();
 
-        self.history
-            .init(&(self.buffer.clone(), self.caret.clone()));
+        if self.undo {
+            self.history.clear();
+            self.history
+                .init(&(self.buffer.clone(), self.caret.clone(), 0));
+        }
 
         Ok(())
     }
 
-    pub fn save(&self) -> IoResult<()> {
-        self.save_as(&self.path)
+    // Writes into a temporary file next to `path`, which is then renamed over it. This way a
+    // crash can't leave a half-written file behind. Falls back to overwriting in place.
+    pub fn save_as(&self, path: &str) -> IoResult<()> {
+        let target = Path::new(path);
+
+        // Renaming would replace a symlink instead of writing to its target
+        let is_symlink = fs::symlink_metadata(target)
+            .map(|meta| meta.file_type().is_symlink())
+            .unwrap_or(false);
+
+        if self.backupcopy || is_symlink {
+            return self.overwrite(path);
+        }
+
+        let name = match target.file_name() {
+            Some(name) => name.to_string_lossy(),
+            None => return self.overwrite(path),
+        };
+        let temp = target.with_file_name(format!(".{}.{}.xim", name, process::id()));
+
+        let written = File::create(&temp).and_then(|mut file| {
+            self.write_to(&mut file)?;
+            file.sync_all()
+        });
+
+        let saved = written
+            .and_then(|_| match fs::metadata(target) {
+                Ok(meta) => fs::set_permissions(&temp, meta.permissions()),
+                // A new file keeps the default permissions
+                Err(_) => Ok(()),
+            })
+            .and_then(|_| fs::rename(&temp, target));
+
+        if saved.is_ok() {
+            return Ok(());
+        }
+
+        // E.g. no permission to create files in the directory, or a different device
+        let _ = fs::remove_file(&temp);
+        self.overwrite(path)
+    }
+
+    fn overwrite(&self, path: &str) -> IoResult<()> {
+        let mut file = File::create(path)?;
+        self.write_to(&mut file)
     }
 
-    pub fn save_as(&self, path: &str) -> IoResult<()> {
-        let mut file = File::create(&path)?;
-        file.write_all(&self.buffer)?;
-        Ok(())
+    // Writes the bytes in `range` to the end of an existing file, which is never truncated
+    pub fn append_to<R: RangeBounds<usize>>(&self, path: &str, range: R) -> IoResult<()> {
+        let mut file = OpenOptions::new().append(true).open(path)?;
+        file.write_all(&self.bytes(range))
+    }
+
+    fn write_to(&self, file: &mut File) -> IoResult<()> {
+        let (front, back) = self.buffer.as_slices();
+        file.write_all(front)?;
+        file.write_all(back)
+    }
+
+    // Creates `.{name}.xim-lock` next to the file, holding our process id. Fails if another,
+    // still running xim holds the lock. Locks of crashed instances are taken over.
+    pub fn lock(&mut self) -> Result<(), String> {
+        self.unlock();
+
+        let target = Path::new(&self.path);
+        let name = match target.file_name() {
+            Some(name) => name.to_string_lossy(),
+            None => return Ok(()),
+        };
+        let lock = target.with_file_name(format!(".{}.xim-lock", name));
+
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&lock) {
+                Ok(mut file) => {
+                    write!(file, "{}", process::id())
+                        .map_err(|e| format!("could not write the lock file: {}", e))?;
+                    self.lock = Some(lock);
+                    return Ok(());
+                }
+                Err(ref e) if e.kind() == ErrorKind::AlreadyExists => {
+                    match fs::read_to_string(&lock).map(|pid| pid.trim().parse::<u32>()) {
+                        Ok(Ok(pid)) if is_running(pid) => {
+                            return Err(format!(
+                                "file is being edited by another xim (process {})",
+                                pid
+                            ));
+                        }
+                        _ => {
+                            let _ = fs::remove_file(&lock);
+                        }
+                    }
+                }
+                Err(e) => return Err(format!("could not create the lock file: {}", e)),
+            }
+        }
+
+        Err("could not take over the lock file".into())
+    }
+
+    pub fn unlock(&mut self) {
+        if let Some(lock) = self.lock.take() {
+            let _ = fs::remove_file(lock);
+        }
     }
 
     // FIXME: better be conservative first...
     pub fn is_modified(&self) -> bool {
-        let disc_content = {
-            let mut file = OpenOptions::new()
-                .create(false)
-                .read(true)
-                .write(false)
-                .open(&self.path)
-                .unwrap();
-            let mut buffer = Vec::new();
-            file.read_to_end(&mut buffer).unwrap();
-            buffer
-        };
+        match fs::read(&self.path) {
+            Ok(disc_content) => self.buffer != disc_content,
+            // E.g. a new file, or deleted behind our back. Nothing is lost if there is no content.
+            Err(_) => !self.buffer.is_empty(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn byte_at(&self, index: usize) -> Option<u8> {
+        self.buffer.get(index)
+    }
 
-        self.buffer != disc_content
+    // Lets `bytes(..)` borrow until the next edit, for the searches running over everything
+    pub fn make_contiguous(&mut self) {
+        self.buffer.make_contiguous();
+    }
+
+    // Borrowed unless the range has to be pieced together
+    pub fn bytes<R: RangeBounds<usize>>(&self, range: R) -> Cow<'_, [u8]> {
+        self.buffer.slice(range)
+    }
+
+    // The last offset the caret can rest on outside of Insert mode
+    pub fn last_offset(&self) -> usize {
+        if self.virtualedit {
+            self.buffer.len()
+        } else {
+            self.buffer.len().saturating_sub(1)
+        }
     }
 
     pub fn set_index(&mut self, new_index: usize) {
@@ -99,7 +283,7 @@ impl Model {
             Caret::Index(index)
             | Caret::Offset(index)
             | Caret::Replace(index)
-            | Caret::Visual(_, index) => index.into(),
+            | Caret::Visual(_, index) => index.value(),
         }
     }
 
@@ -121,47 +305,92 @@ impl Model {
         }
     }
 
-    pub fn snapshot(&mut self) {
+    // Number of undo steps to keep, see `History::set_limit`
+    pub fn set_undolevels(&mut self, levels: usize) {
+        self.history.set_limit(levels);
+    }
+
+    // Disabling drops the whole history, enabling starts a new one at the current state
+    pub fn set_undo(&mut self, undo: bool, scroll_start: usize) {
+        if undo == self.undo {
+            return;
+        }
+
+        self.undo = undo;
+        self.history.clear();
+        self.snapshot(scroll_start);
+    }
+
+    pub fn undo_enabled(&self) -> bool {
+        self.undo
+    }
+
+    // The scroll position is stored alongside, so undo/redo can bring back the same view.
+    pub fn snapshot(&mut self, scroll_start: usize) {
+        if !self.undo {
+            return;
+        }
+
         self.history
-            .snapshot(&(self.buffer.clone(), self.caret.clone()));
+            .snapshot(&(self.buffer.clone(), self.caret.clone(), scroll_start));
     }
 
-    pub fn undo(&mut self) -> bool {
-        if let Some((older_buffer, older_caret)) = self.history.undo() {
+    // Returns the scroll position of the restored snapshot
+    pub fn undo(&mut self) -> Option<usize> {
+        if let Some((older_buffer, older_caret, scroll_start)) = self.history.undo() {
             self.buffer = older_buffer;
             self.caret = older_caret;
-            true
+            Some(scroll_start)
         } else {
-            false
+            None
         }
     }
 
-    pub fn redo(&mut self) -> bool {
-        if let Some((newer_buffer, newer_caret)) = self.history.redo() {
+    // Returns the scroll position of the restored snapshot
+    pub fn redo(&mut self) -> Option<usize> {
+        if let Some((newer_buffer, newer_caret, scroll_start)) = self.history.redo() {
             self.buffer = newer_buffer;
             self.caret = newer_caret;
-            true
+            Some(scroll_start)
         } else {
-            false
+            None
         }
     }
 
-    pub fn edit(&mut self, mut start: usize, mut end: usize, new: &[u8]) -> Result<(), String> {
+    pub fn edit(&mut self, mut start: usize, mut end: usize, new: &[u8]) -> Result<(), EditError> {
         if start > end {
             swap(&mut start, &mut end);
         }
 
-        // Will eventually be replaced by ropes...
         if end <= self.buffer.len() {
-            self.buffer.splice(start..end, new.iter().cloned());
+            self.buffer.splice(start..end, new);
         } else {
-            return Err("no data to edit".into());
+            return Err(EditError::OutOfBounds {
+                start,
+                end,
+                len: self.buffer.len(),
+            });
+        }
+
+        // Notes behind the edit move along with their bytes, notes on deleted bytes are dropped
+        let kept = min(new.len(), end - start);
+        if kept != end - start || kept != new.len() {
+            self.notes = self
+                .notes
+                .drain()
+                .filter_map(|(offset, note)| match offset {
+                    offset if offset < start + kept => Some((offset, note)),
+                    offset if offset < end => None,
+                    offset => Some((offset - end + start + new.len(), note)),
+                })
+                .collect();
         }
 
+        let last_offset = self.last_offset();
         match self.caret {
             Caret::Index(ref mut index) => index.set_maximum(self.buffer.len()),
             Caret::Offset(ref mut index) | Caret::Replace(ref mut index) => {
-                index.set_maximum(self.buffer.len().saturating_sub(1))
+                index.set_maximum(last_offset)
             }
             Caret::Visual(ref mut start, ref mut end) => {
                 start.set_maximum(self.buffer.len().saturating_sub(1));
@@ -173,11 +402,200 @@ impl Model {
     }
 }
 
+impl Drop for Model {
+    fn drop(&mut self) {
+        self.unlock();
+    }
+}
+
+// Without procfs there is no telling, so any process might be running
+fn is_running(pid: u32) -> bool {
+    let proc = Path::new("/proc");
+    !proc.is_dir() || proc.join(pid.to_string()).exists()
+}
+
 #[cfg(test)]
 mod tests {
     use quickcheck_macros::quickcheck;
 
     use super::*;
+    use crate::TempFile;
+
+    #[test]
+    fn test_save_as_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = TempFile::new("save");
+        fs::write(&path, [0xaa]).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        for &backupcopy in &[false, true] {
+            let mut model = Model::new();
+            model.buffer = vec![0, 1, 2].into();
+            model.backupcopy = backupcopy;
+            model.save_as(path.path()).unwrap();
+
+            assert_eq!(fs::read(&path).unwrap(), vec![0, 1, 2]);
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o640);
+        }
+    }
+
+    // Bytes that aren't edited reach the disk as they were read, e.g. no line ending is added
+    // or converted. Both saving strategies are covered.
+    fn round_trip(name: &str, bytes: &[u8], backupcopy: bool) -> bool {
+        let from = TempFile::new(name);
+        let to = TempFile::new(&format!("{}-out", name));
+        fs::write(&from, bytes).unwrap();
+
+        let mut model = Model::new();
+        model.backupcopy = backupcopy;
+        model.open(from.path()).unwrap();
+        model.save_as(to.path()).unwrap();
+        fs::read(&to).unwrap() == bytes
+    }
+
+    #[quickcheck]
+    fn test_round_trip(bytes: Vec<u8>, backupcopy: bool) -> bool {
+        round_trip("round-trip", &bytes, backupcopy)
+    }
+
+    #[test]
+    fn test_round_trip_edge_cases() {
+        for bytes in [&b""[..], b"no newline", b"\n", b"\r\n\r", b"\x00\xff\x1a"] {
+            assert!(round_trip("round-trip-edge", bytes, false));
+            assert!(round_trip("round-trip-edge", bytes, true));
+        }
+    }
+
+    #[test]
+    fn test_save_as_failed_removes_temp() {
+        let dir = TempFile::new("save-dir");
+        fs::create_dir(&dir).unwrap();
+
+        let mut model = Model::new();
+        model.buffer = vec![0].into();
+        // Neither renamed nor written over, as it's a directory
+        assert!(model.save_as(dir.path()).is_err());
+
+        let name = Path::new(dir.path()).file_name().unwrap().to_str().unwrap();
+        let temp = Path::new(dir.path()).with_file_name(format!(".{}.{}.xim", name, process::id()));
+        assert!(!temp.exists());
+
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_append_to() {
+        let file = TempFile::new("append");
+        let path = file.path();
+
+        let mut model = Model::new();
+        model.buffer = vec![0, 1, 2, 3].into();
+        assert!(model.append_to(path, ..).is_err());
+
+        fs::write(path, [0xaa]).unwrap();
+        model.append_to(path, ..).unwrap();
+        model.append_to(path, 1..3).unwrap();
+        assert_eq!(fs::read(path).unwrap(), vec![0xaa, 0, 1, 2, 3, 1, 2]);
+    }
+
+    #[test]
+    fn test_lock() {
+        let file = TempFile::new("lock");
+        let path = file.path();
+        // Named by the model, next to the file
+        let lock = Path::new(path).with_file_name(format!(
+            ".{}.xim-lock",
+            Path::new(path).file_name().unwrap().to_str().unwrap()
+        ));
+
+        let mut first = Model::new();
+        first.path = path.into();
+        first.lock().unwrap();
+        assert_eq!(
+            fs::read_to_string(&lock).unwrap(),
+            process::id().to_string()
+        );
+
+        let mut second = Model::new();
+        second.path = path.into();
+        assert_eq!(
+            second.lock(),
+            Err(format!(
+                "file is being edited by another xim (process {})",
+                process::id()
+            ))
+        );
+
+        // Only the owner removes the lock
+        drop(second);
+        assert!(lock.exists());
+        drop(first);
+        assert!(!lock.exists());
+
+        // The lock of a process that is gone is taken over
+        fs::write(&lock, "4000000000").unwrap();
+        let mut third = Model::new();
+        third.path = path.into();
+        third.lock().unwrap();
+        third.unlock();
+        assert!(!lock.exists());
+    }
+
+    #[test]
+    fn test_undo_restores_scroll_start() {
+        let mut model = Model::new();
+        model.snapshot(0);
+
+        model.edit(0, 0, &[0; 64]).unwrap();
+        model.snapshot(32);
+        model.edit(0, 0, &[1]).unwrap();
+        model.snapshot(48);
+
+        assert_eq!(model.undo(), Some(32));
+        assert_eq!(model.buffer, vec![0; 64]);
+        assert_eq!(model.undo(), Some(0));
+        assert_eq!(model.undo(), None);
+        assert_eq!(model.redo(), Some(32));
+        assert_eq!(model.redo(), Some(48));
+        assert_eq!(model.redo(), None);
+    }
+
+    #[test]
+    fn test_accessors() {
+        let mut model = Model::new();
+        assert_eq!(model.len(), 0);
+        assert!(model.is_empty());
+        assert_eq!(model.byte_at(0), None);
+
+        model.edit(0, 0, &[0xaa, 0xbb]).unwrap();
+        assert_eq!(model.len(), 2);
+        assert!(!model.is_empty());
+        assert_eq!(model.byte_at(1), Some(0xbb));
+        assert_eq!(model.byte_at(2), None);
+    }
+
+    #[test]
+    fn test_edit_moves_notes() {
+        let mut model = Model::new();
+        model.buffer = vec![0; 8].into();
+        for offset in [1, 3, 6] {
+            model.notes.insert(offset, offset.to_string());
+        }
+
+        // Replacing keeps them, deleting drops the deleted notes and moves the ones behind
+        model.edit(1, 2, &[0xff]).unwrap();
+        model.edit(2, 4, &[]).unwrap();
+        assert_eq!(model.notes.get(&1).map(|note| &note[..]), Some("1"));
+        assert_eq!(model.notes.get(&4).map(|note| &note[..]), Some("6"));
+        assert_eq!(model.notes.len(), 2);
+
+        model.edit(0, 0, &[0; 3]).unwrap();
+        let mut offsets: Vec<_> = model.notes.keys().cloned().collect();
+        offsets.sort();
+        assert_eq!(offsets, vec![4, 7]);
+    }
 
     #[quickcheck]
     fn test_edit(buffer: Vec<u8>, start: usize, end: usize, new: Vec<u8>) -> bool {
@@ -186,15 +604,23 @@ mod tests {
         let mut model = Model {
             path: "".into(),
             caret: Caret::Offset(UsizeMax::new(0, buffer.len())),
-            buffer: buffer.clone(),
+            buffer: buffer.clone().into(),
+            backupcopy: false,
+            swapfile: true,
+            lock: None,
+            undo: true,
             history: History::new(),
-            term_size: (0, 0),
+            notes: HashMap::new(),
+            virtualedit: false,
         };
 
         if start <= buffer.len() && end <= buffer.len() && start <= end {
             model.edit(start, end, &new).unwrap();
             buffer.splice(start..end, new.iter().cloned());
-            buffer == model.buffer
+            model.buffer == buffer
+        } else if start <= end {
+            let len = buffer.len();
+            model.edit(start, end, &new) == Err(EditError::OutOfBounds { start, end, len })
         } else {
             true
         }
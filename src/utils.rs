@@ -1,18 +1,29 @@
-use std::cmp::min;
+use std::{
+    cmp::min,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
 
 use clipboard::{ClipboardContext, ClipboardProvider};
+use termion::event::Key;
 
-pub fn move_window(start: usize, height: usize, new_index: usize) -> Option<usize> {
+use crate::Ascii;
+
+// Keeps `new_index` at least `margin` rows away from the edges of the window, if it is high enough
+pub fn move_window(start: usize, height: usize, new_index: usize, margin: usize) -> Option<usize> {
     if height == 0 {
         return None;
     }
 
+    let margin = min(margin, (height - 1) / 2);
     let mut new_start = start;
 
-    if new_index < start {
-        new_start = new_index;
-    } else if new_index > start + (height.saturating_sub(1)) {
-        new_start = new_index - (height.saturating_sub(1));
+    if new_index < start.saturating_add(margin) {
+        new_start = new_index.saturating_sub(margin);
+    } else if new_index > start.saturating_add(height - 1 - margin) {
+        new_start = new_index - (height - 1 - margin);
     }
 
     Some(new_start)
@@ -34,23 +45,80 @@ pub fn align_top(value: u16, boundary: u16) -> u16 {
     }
 }
 
+// Whether there's a system clipboard to connect to, e.g. not on a server without X
+pub fn has_system_clipboard() -> bool {
+    let cb: Result<ClipboardContext, _> = ClipboardProvider::new();
+    cb.is_ok()
+}
+
 pub fn save_to_clipboard(data: &[u8]) -> Result<String, String> {
     let cb: Result<ClipboardContext, _> = ClipboardProvider::new().map_err(|e| format!("{}", e));
     let mut cb = cb?;
 
-    match cb.set_contents(hex::encode(data)) {
-        Ok(_) => match data.len() {
-            0 => Err("No data to copy".into()),
-            1 => Ok(format!("Copied to clipboard ({})", hex::encode(&data[..1]))),
-            _ => Ok(format!(
-                "Copied to clipboard ({}...)",
-                hex::encode(&data[..min(data.len(), 3)])
-            )),
-        },
+    match cb.set_contents(encode_hex(data)) {
+        Ok(_) => describe_copy(data, "clipboard"),
         Err(e) => Err(format!("Failed copy to clipboard ({})", e)),
     }
 }
 
+// Asks the terminal to copy `data` as hex, like the system clipboard. Through SSH as well,
+// as it only takes writing to the terminal.
+pub fn osc52_copy(data: &[u8]) -> String {
+    format!(
+        "\x1b]52;c;{}\x07",
+        encode_base64(encode_hex(data).as_bytes())
+    )
+}
+
+pub fn encode_base64(data: &[u8]) -> String {
+    const DIGITS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+            group | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(DIGITS[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+// The status after copying `data` to `target`, with its first bytes
+pub fn describe_copy(data: &[u8], target: &str) -> Result<String, String> {
+    match data.len() {
+        0 => Err("No data to copy".into()),
+        1 => Ok(format!(
+            "Copied to {} ({})",
+            target,
+            hex::encode(&data[..1])
+        )),
+        _ => Ok(format!(
+            "Copied to {} ({}...)",
+            target,
+            hex::encode(&data[..min(data.len(), 3)])
+        )),
+    }
+}
+
+// Like `hex::encode`, but allocates the result only once, which matters for large selections
+pub fn encode_hex(data: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+    let mut encoded = String::with_capacity(data.len() * 2);
+    for &byte in data {
+        encoded.push(DIGITS[(byte >> 4) as usize] as char);
+        encoded.push(DIGITS[(byte & 0x0f) as usize] as char);
+    }
+
+    encoded
+}
+
 pub fn read_from_clipboard() -> Result<Vec<u8>, String> {
     let cb: Result<ClipboardContext, _> = ClipboardProvider::new().map_err(|e| format!("{}", e));
     let mut cb = cb?;
@@ -61,16 +129,243 @@ pub fn read_from_clipboard() -> Result<Vec<u8>, String> {
     hex::decode(&data).map_err(|e| format!("{}", e))
 }
 
+// Runs `cmd` in a shell with `input` on stdin and returns its stdout.
+// Nothing is inherited from the terminal, so the raw screen stays untouched.
+pub fn filter_through(cmd: &str, input: &[u8]) -> Result<Vec<u8>, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run \"{}\" ({})", cmd, e))?;
+
+    // Feed stdin from another thread, otherwise a full stdout pipe could deadlock us.
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_owned();
+    let feeder = thread::spawn(move || {
+        // A command may exit without reading everything, that's not an error.
+        let _ = stdin.write_all(&input);
+    });
+
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("could not run \"{}\" ({})", cmd, e))?;
+    let _ = feeder.join();
+
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.lines().next().unwrap_or("").trim().to_owned();
+        match output.status.code() {
+            Some(code) => Err(format!("\"{}\" exited with {} {}", cmd, code, reason)),
+            None => Err(format!("\"{}\" was terminated by a signal", cmd)),
+        }
+    }
+}
+
+// Runs `editor` on the file at `path` with the terminal's stdin and stdout, e.g. `HEXEDITOR="hexedit -s"`
+pub fn edit_with(editor: &str, path: &Path) -> Result<(), String> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .map_err(|e| format!("could not run \"{}\" ({})", editor, e))?;
+
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(code) => Err(format!("\"{}\" exited with {}", editor, code)),
+        None => Err(format!("\"{}\" was terminated by a signal", editor)),
+    }
+}
+
+// Whether `bytes` start with `pattern`, where `None` matches any byte. With `ignorecase`,
+// ASCII letters match in either case.
+fn starts_with(bytes: &[u8], pattern: &[Option<u8>], ignorecase: bool) -> bool {
+    bytes.len() >= pattern.len()
+        && bytes
+            .iter()
+            .zip(pattern)
+            .all(|(byte, expected)| match expected {
+                Some(expected) => {
+                    byte == expected || ignorecase && byte.eq_ignore_ascii_case(expected)
+                }
+                None => true,
+            })
+}
+
+// Finds the first occurrence of `pattern` starting at or after `from`
+pub fn find_next(
+    buffer: &[u8],
+    pattern: &[Option<u8>],
+    from: usize,
+    ignorecase: bool,
+) -> Option<usize> {
+    if pattern.is_empty() {
+        return None;
+    }
+
+    buffer
+        .get(from..)?
+        .windows(pattern.len())
+        .position(|window| starts_with(window, pattern, ignorecase))
+        .map(|position| position + from)
+}
+
+// Finds the last occurrence of `pattern` starting before `before`
+pub fn find_prev(
+    buffer: &[u8],
+    pattern: &[Option<u8>],
+    before: usize,
+    ignorecase: bool,
+) -> Option<usize> {
+    if pattern.is_empty() {
+        return None;
+    }
+
+    (0..min(before, buffer.len()))
+        .rev()
+        .find(|&start| starts_with(&buffer[start..], pattern, ignorecase))
+}
+
+// Formats `data` like `xxd -c width`, with offsets counting from `start`
+pub fn xxd_dump(data: &[u8], start: usize, width: usize) -> String {
+    let mut dump = String::new();
+
+    for (row, chunk) in data.chunks(width).enumerate() {
+        dump.push_str(&format!("{:08x}: ", start + row * width));
+
+        for column in 0..width {
+            match chunk.get(column) {
+                Some(byte) => dump.push_str(&format!("{:02x}", byte)),
+                None => dump.push_str("  "),
+            }
+            if column % 2 == 1 || column == width - 1 {
+                dump.push(' ');
+            }
+        }
+
+        dump.push(' ');
+        dump.extend(chunk.iter().map(|byte| byte.to_printable()));
+        dump.push('\n');
+    }
+
+    dump
+}
+
+// Counts the (possibly overlapping) occurrences of `pattern`, returns the number of the one at
+// `offset` and the total
+pub fn count_matches(
+    buffer: &[u8],
+    pattern: &[Option<u8>],
+    offset: usize,
+    ignorecase: bool,
+) -> (usize, usize) {
+    if pattern.is_empty() {
+        return (0, 0);
+    }
+
+    buffer
+        .windows(pattern.len())
+        .enumerate()
+        .filter(|(_, window)| starts_with(window, pattern, ignorecase))
+        .fold((0, 0), |(current, total), (start, _)| {
+            (current + (start <= offset) as usize, total + 1)
+        })
+}
+
+// Shannon entropy of `data` in bits per byte
+pub fn entropy(data: &[u8]) -> f64 {
+    let mut histogram = [0usize; 256];
+    for &byte in data {
+        histogram[byte as usize] += 1;
+    }
+
+    histogram
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / data.len() as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+// Formats a byte in all common bases, e.g. "0x41 65 0o101 0b01000001 'A'"
+// The decimal is the two's complement with `signed`, e.g. -1 for 0xff
+pub fn describe_byte(byte: u8, signed: bool) -> String {
+    let decimal = if signed {
+        (byte as i8).to_string()
+    } else {
+        byte.to_string()
+    };
+    let mut description = format!("0x{:02x} {} 0o{:o} 0b{:08b}", byte, decimal, byte, byte);
+    if (32..=126).contains(&byte) {
+        description.push_str(&format!(" '{}'", byte as char));
+    }
+    description
+}
+
 pub fn offset_width(max: usize) -> u16 {
     format!("{:x}", max).len() as u16
 }
 
+// Reads a key back from its `Debug` output, e.g. `Char('x')` or `Ctrl('\n')`, as in the log
+pub fn parse_key(text: &str) -> Option<Key> {
+    use Key::*;
+
+    let named = [
+        Backspace, Left, Right, Up, Down, Home, End, PageUp, PageDown, BackTab, Delete, Insert,
+        Null, Esc,
+    ];
+    if let Some(&key) = named.iter().find(|key| format!("{:?}", key) == text) {
+        return Some(key);
+    }
+
+    let (name, argument) = text.strip_suffix(')')?.split_once('(')?;
+    match name {
+        "F" => argument.parse().ok().map(F),
+        "Char" => parse_char(argument).map(Char),
+        "Alt" => parse_char(argument).map(Alt),
+        "Ctrl" => parse_char(argument).map(Ctrl),
+        _ => None,
+    }
+}
+
+// The inverse of `char::escape_debug` in quotes
+fn parse_char(quoted: &str) -> Option<char> {
+    let inner = quoted.strip_prefix('\'')?.strip_suffix('\'')?;
+    let mut chars = inner.chars();
+
+    match (chars.next()?, chars.as_str()) {
+        ('\\', "n") => Some('\n'),
+        ('\\', "r") => Some('\r'),
+        ('\\', "t") => Some('\t'),
+        ('\\', "0") => Some('\0'),
+        ('\\', escaped @ ("\\" | "'" | "\"")) => escaped.chars().next(),
+        ('\\', escaped) => {
+            let code = escaped.strip_prefix("u{")?.strip_suffix('}')?;
+            std::char::from_u32(u32::from_str_radix(code, 16).ok()?)
+        }
+        (c, "") => Some(c),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use quickcheck_macros::quickcheck;
 
     use super::*;
 
+    fn exact(bytes: &[u8]) -> Vec<Option<u8>> {
+        bytes.iter().cloned().map(Some).collect()
+    }
+
     #[quickcheck]
     fn test_align(index: u16, random: u16, boundary: u16) -> bool {
         match boundary {
@@ -92,9 +387,140 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_filter_through() {
+        assert_eq!(filter_through("tr a-z A-Z", b"xim"), Ok(b"XIM".to_vec()));
+        assert_eq!(filter_through("cat", b""), Ok(vec![]));
+        assert!(filter_through("exit 3", b"xim")
+            .unwrap_err()
+            .contains("exited with 3"));
+    }
+
+    #[test]
+    fn test_encode_base64() {
+        assert_eq!(encode_base64(b""), "");
+        assert_eq!(encode_base64(b"f"), "Zg==");
+        assert_eq!(encode_base64(b"fo"), "Zm8=");
+        assert_eq!(encode_base64(b"foo"), "Zm9v");
+        assert_eq!(encode_base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(encode_base64(&[0xff, 0xfe]), "//4=");
+        assert_eq!(osc52_copy(&[0xab]), "\x1b]52;c;YWI=\x07");
+    }
+
+    #[test]
+    fn test_describe_byte() {
+        assert_eq!(describe_byte(0x41, false), "0x41 65 0o101 0b01000001 'A'");
+        assert_eq!(describe_byte(0x00, false), "0x00 0 0o0 0b00000000");
+        assert_eq!(describe_byte(0xff, false), "0xff 255 0o377 0b11111111");
+        assert_eq!(describe_byte(0xff, true), "0xff -1 0o377 0b11111111");
+        assert_eq!(describe_byte(0x80, true), "0x80 -128 0o200 0b10000000");
+        assert_eq!(describe_byte(0x7f, true), "0x7f 127 0o177 0b01111111");
+    }
+
+    #[test]
+    fn test_find() {
+        let buffer = [0, 1, 2, 0, 1, 2, 3];
+
+        assert_eq!(find_next(&buffer, &exact(&[1, 2]), 0, false), Some(1));
+        assert_eq!(find_next(&buffer, &exact(&[1, 2]), 2, false), Some(4));
+        assert_eq!(find_next(&buffer, &exact(&[1, 2]), 5, false), None);
+        assert_eq!(find_next(&buffer, &exact(&[1, 2]), 100, false), None);
+        assert_eq!(find_next(&buffer, &exact(&[2, 3]), 0, false), Some(5));
+        assert_eq!(find_prev(&buffer, &exact(&[1, 2]), 7, false), Some(4));
+        assert_eq!(find_prev(&buffer, &exact(&[1, 2]), 4, false), Some(1));
+        assert_eq!(find_prev(&buffer, &exact(&[1, 2]), 1, false), None);
+        assert_eq!(find_prev(&buffer, &exact(&[2, 3]), 100, false), Some(5));
+        assert_eq!(find_next(&buffer, &exact(&[]), 0, false), None);
+        assert_eq!(find_prev(&buffer, &exact(&[]), 7, false), None);
+    }
+
+    #[test]
+    fn test_find_wildcard() {
+        let buffer = [0xde, 0xad, 0xef, 0xde, 0x00, 0xef];
+        let pattern = [Some(0xde), None, Some(0xef)];
+        assert_eq!(find_next(&buffer, &pattern, 1, false), Some(3));
+        assert_eq!(find_prev(&buffer, &pattern, 3, false), Some(0));
+        assert_eq!(count_matches(&buffer, &pattern, 3, false), (2, 2));
+        assert_eq!(find_next(&buffer, &[None, None], 4, false), Some(4));
+        assert_eq!(find_next(&buffer, &[None, None], 5, false), None);
+    }
+
+    #[test]
+    fn test_find_ignorecase() {
+        let buffer = b"xAbyaBzab1";
+        assert_eq!(find_next(buffer, &exact(b"ab"), 0, false), Some(7));
+        assert_eq!(find_next(buffer, &exact(b"ab"), 0, true), Some(1));
+        assert_eq!(find_prev(buffer, &exact(b"AB"), 7, true), Some(4));
+        assert_eq!(count_matches(buffer, &exact(b"ab"), 4, true), (2, 3));
+        // Only letters are folded, '{' is not a lower case '['
+        assert_eq!(find_next(b"{[", &exact(b"["), 0, true), Some(1));
+    }
+
+    #[test]
+    fn test_count_matches() {
+        let buffer = [1, 1, 1, 2, 1, 1];
+        assert_eq!(count_matches(&buffer, &exact(&[1, 1]), 0, false), (1, 3));
+        assert_eq!(count_matches(&buffer, &exact(&[1, 1]), 1, false), (2, 3));
+        assert_eq!(count_matches(&buffer, &exact(&[1, 1]), 4, false), (3, 3));
+        assert_eq!(count_matches(&buffer, &exact(&[3]), 0, false), (0, 0));
+        assert_eq!(count_matches(&buffer, &exact(&[]), 0, false), (0, 0));
+    }
+
+    #[test]
+    fn test_xxd_dump() {
+        assert_eq!(
+            xxd_dump(b"Hello, world! This is xim.", 0, 16),
+            "00000000: 4865 6c6c 6f2c 2077 6f72 6c64 2120 5468  Hello, world! Th\n\
+             00000010: 6973 2069 7320 7869 6d2e                 is is xim.\n"
+        );
+        assert_eq!(
+            xxd_dump(b"Hello", 0, 3),
+            "00000000: 4865 6c  Hel\n00000003: 6c6f     lo\n"
+        );
+        assert_eq!(xxd_dump(b"\x00", 0x20, 1), "00000020: 00  .\n");
+        assert_eq!(xxd_dump(b"", 0, 16), "");
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(entropy(&[]), 0.0);
+        assert_eq!(entropy(&[7; 16]), 0.0);
+        assert_eq!(entropy(&[0, 1, 0, 1]), 1.0);
+        assert_eq!(entropy(&(0..16).collect::<Vec<_>>()), 4.0);
+        assert_eq!(entropy(&(0..=255).collect::<Vec<_>>()), 8.0);
+    }
+
+    #[quickcheck]
+    fn test_parse_key(c: char, f: u8) -> bool {
+        use termion::event::Key::*;
+
+        [Char(c), Alt(c), Ctrl(c), F(f), Esc, BackTab, Left]
+            .iter()
+            .all(|&key| parse_key(&format!("{:?}", key)) == Some(key))
+    }
+
+    #[test]
+    fn test_parse_key_escapes() {
+        for &c in &['\n', '\'', '\\', '"', '\u{1b}', '\u{7f}', ' ', ')'] {
+            assert_eq!(
+                parse_key(&format!("{:?}", Key::Char(c))),
+                Some(Key::Char(c))
+            );
+        }
+        assert_eq!(parse_key("Char('ab')"), None);
+        assert_eq!(parse_key("Shift('a')"), None);
+        assert_eq!(parse_key("Enter"), None);
+    }
+
+    #[quickcheck]
+    fn test_encode_hex(data: Vec<u8>) -> bool {
+        let encoded = encode_hex(&data);
+        encoded == hex::encode(&data) && encoded.capacity() == data.len() * 2
+    }
+
     #[quickcheck]
     fn test_move_window(start: usize, height: usize, index: usize) -> bool {
-        if let Some(new_start) = move_window(start, height, index) {
+        if let Some(new_start) = move_window(start, height, index, 0) {
             // Do not move when unnecessary...
             if start <= index && index <= start + (height - 1) {
                 new_start == start
@@ -107,4 +533,18 @@ mod tests {
             height == 0
         }
     }
+
+    #[quickcheck]
+    fn test_move_window_margin(start: u16, height: u16, index: u16, margin: u16) -> bool {
+        let (start, height, index) = (start as usize, height as usize, index as usize);
+
+        match move_window(start, height, index, margin as usize) {
+            Some(new_start) => {
+                let margin = min(margin as usize, (height - 1) / 2);
+                (new_start + margin <= index || new_start == 0)
+                    && index + margin <= new_start + (height - 1)
+            }
+            None => height == 0,
+        }
+    }
 }
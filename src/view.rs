@@ -1,28 +1,34 @@
 use std::{
+    cell::RefCell,
     cmp::{max, min},
     io::{Result as IoResult, Write},
+    iter::repeat_n,
     mem::swap,
 };
 
 use termion::{
     clear::{All as ClearAll, CurrentLine as ClearCurrentLine},
-    color::{Fg, Red, Reset as ColorReset},
+    color::{AnsiValue, Bg, Fg, Red, Reset as ColorReset, Yellow},
     cursor::Goto,
-    style::{Bold, Invert, NoInvert, Reset as StyleReset, Underline},
+    style::{Bold, Faint, Invert, NoInvert, NoUnderline, Reset as StyleReset, Underline},
 };
+use unicode_width::UnicodeWidthChar;
 
 use crate::{
     model::{Caret, Model},
-    utils::{align, align_top, move_window, offset_width},
+    utils::{align, align_top, describe_byte, entropy, move_window, offset_width},
     Ascii, RawStdout,
 };
 
 fn chunks_indices(mut start: u16, end: u16, size: u16) -> Vec<(u16, u16)> {
-    let mut result = Vec::with_capacity(((end - start) / 16) as usize);
+    let mut result = Vec::with_capacity(((end - start) / size) as usize);
 
     while start <= end {
-        result.push((start, min(start + size - 1, end)));
-        start += size;
+        result.push((start, min(start.saturating_add(size - 1), end)));
+        start = match start.checked_add(size) {
+            Some(next) => next,
+            None => break,
+        };
     }
 
     result
@@ -33,18 +39,201 @@ pub fn range_to_marker(mut start: u16, mut end: u16) -> Vec<(u16, u16, u16)> {
         swap(&mut start, &mut end);
     };
 
-    let lines = (start / 16..end / 16 + 1).collect::<Vec<_>>();
-    let mut spans = chunks_indices(align(start, 16), align_top(end, 16), 16);
-    spans.first_mut().unwrap().0 += start;
-    spans.last_mut().unwrap().1 = end % 16;
+    let row = BYTES_PER_ROW as u16;
+    let lines = (start / row..=end / row).collect::<Vec<_>>();
+
+    // There is always at least one span, because align(start) <= align_top(end).
+    let mut spans = chunks_indices(align(start, row), align_top(end, row), row);
+    spans.first_mut().unwrap().0 = start;
+    spans.last_mut().unwrap().1 = end;
 
     lines
         .iter()
         .zip(spans.iter())
-        .map(|(line, &(x, y))| (*line, x % 16, y % 16))
+        .map(|(line, &(x, y))| (*line, x % row, y % row))
         .collect()
 }
 
+// Number of characters `text` takes on screen, escape sequences (e.g. for bold text) take none
+fn visible_len(text: &str) -> usize {
+    let mut len = 0;
+    let mut escape = false;
+
+    for c in text.chars() {
+        match c {
+            '\x1b' => escape = true,
+            c if escape => escape = !c.is_ascii_alphabetic(),
+            _ => len += 1,
+        }
+    }
+
+    len
+}
+
+// Cuts `text` down to `width` visible characters, ending with an ellipsis if shortened
+fn clip(text: &str, width: usize) -> String {
+    if visible_len(text) <= width {
+        return text.into();
+    }
+    if width == 0 {
+        return "".into();
+    }
+
+    let mut clipped = String::new();
+    let mut len = 0;
+    let mut escape = false;
+
+    for c in text.chars() {
+        match c {
+            '\x1b' => escape = true,
+            c if escape => escape = !c.is_ascii_alphabetic(),
+            _ if len + 1 == width => break,
+            _ => len += 1,
+        }
+        clipped.push(c);
+    }
+
+    format!("{}…{}", clipped, StyleReset)
+}
+
+// Smallest terminal width to draw into. The full layout takes 75 columns with 8 digit offsets,
+// narrower terminals get its rows cut off at the right edge.
+const MIN_WIDTH: u16 = 40;
+// The index header and one row, the status lines come on top
+const MIN_HEX_HEIGHT: u16 = 2;
+
+// The layout and the movements between rows depend on this
+pub const BYTES_PER_ROW: usize = 16;
+
+// Shown by `:help` and `?`, clipped like the rows on narrow terminals
+const HELP: &[&str] = &[
+    "Normal mode",
+    "  h j k l, arrows      move by byte or row, Enter to the next row",
+    "  G, 42G               go to the last byte, to byte 42",
+    "  f ab, ;              find the next byte ab, again",
+    "  / ab ?? cd, n, N     search a hex pattern (?? is any byte), next, previous",
+    "  Ctrl-o, Ctrl-i       back and forth in the jump list",
+    "  i, a, gI, A          insert, append, at the start, at the end",
+    "  r, R                 replace a byte, replace mode",
+    "  x, d + motion        delete a byte, up to the motion",
+    "  y + motion, p, P     yank, paste after, before",
+    "                       motions: l w e 0 $ G, or d/y for the row",
+    "  u, Ctrl-r            undo, redo",
+    "  v                    visual mode",
+    "  Tab                  switch between hex and ASCII",
+    "  Ctrl-c               copy to the clipboard",
+    "  Ctrl-l               redraw",
+    "  ?                    this help",
+    "",
+    "Visual mode",
+    "  o                    go to the other end",
+    "  iw                   select the word at the caret",
+    "  y, d or x            yank, delete",
+    "  u, U                 lower, upper case",
+    "  Ctrl-c, Ctrl-v       copy, replace with the clipboard",
+    "",
+    "Insert mode",
+    "  Ctrl-v               paste from the clipboard",
+    "  Ctrl-q ab            insert the byte ab literally",
+    "",
+    "Commands",
+    "  :w, :w file          save, save as (:w! to overwrite)",
+    "  :w >> file           append to a file",
+    "  :q, :q!, :wq         quit, without saving, after saving",
+    "  :e file              open another file",
+    "  :export xxd file     export as xxd dump",
+    "  :0x10, :+16, :50%    jump to an offset",
+    "  :select 0x10 0x2f    select a range",
+    "  :grep regex          search with a regular expression, n and N step",
+    "  :list ab ?? cd       list the offsets of all matches",
+    "  :sort, :reverse      reorder the selection",
+    "  :swap 4              swap the byte order in groups",
+    "  :!cmd                filter through a shell command",
+    "  :copy, :info         copy to the clipboard, show statistics",
+    "  :note text, :note    note the byte at the caret, remove the note",
+    "  :map x cmd           run a command with a key",
+    "  :ascii, :ascii A     ASCII reference, code of a character",
+    "  :edit-external       edit in $HEXEDITOR or $EDITOR",
+    "  :set option value    e.g. snap, wrapscan, incsearch, undolevels",
+];
+
+// Drops whatever would be written at or beyond column `right`, so the terminal can't wrap it
+// onto the next line. Follows the cursor through `Goto`, other escape sequences pass as they are.
+struct ClipRight<'a> {
+    inner: &'a mut dyn Write,
+    right: u16,
+    col: u16,
+    // The escape sequence being read, if any
+    escape: Option<Vec<u8>>,
+    // Whether the rest of a multi-byte character is dropped
+    dropping: bool,
+}
+
+impl<'a> ClipRight<'a> {
+    fn new(inner: &'a mut dyn Write, right: u16) -> ClipRight<'a> {
+        ClipRight {
+            inner,
+            right,
+            col: 1,
+            escape: None,
+            dropping: false,
+        }
+    }
+
+    // Moves `col` if the finished sequence is a `Goto`, i.e. `ESC [ row ; col H`
+    fn track(&mut self, sequence: &[u8]) {
+        if let Some(params) = sequence
+            .strip_prefix(b"\x1b[")
+            .and_then(|rest| rest.strip_suffix(b"H"))
+        {
+            let params = String::from_utf8_lossy(params);
+            if let Some(Ok(col)) = params.split(';').nth(1).map(str::parse) {
+                self.col = col;
+            }
+        }
+    }
+}
+
+impl<'a> Write for ClipRight<'a> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let mut out = Vec::with_capacity(buf.len());
+
+        for &byte in buf {
+            if let Some(mut sequence) = self.escape.take() {
+                sequence.push(byte);
+                // A CSI ends with a letter, anything else with its first character after ESC
+                let done = byte.is_ascii_alphabetic() || (sequence.len() == 2 && byte != b'[');
+                if done {
+                    self.track(&sequence);
+                    out.extend_from_slice(&sequence);
+                } else {
+                    self.escape = Some(sequence);
+                }
+            } else if byte == 0x1b {
+                self.escape = Some(vec![byte]);
+            } else if byte & 0xc0 == 0x80 {
+                // Continues the character before, which took the column already
+                if !self.dropping {
+                    out.push(byte);
+                }
+            } else {
+                self.dropping = self.col >= self.right;
+                if !self.dropping {
+                    out.push(byte);
+                }
+                self.col = self.col.saturating_add(1);
+            }
+        }
+
+        self.inner.write_all(&out)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.inner.flush()
+    }
+}
+
 pub struct DrawArea {
     pub origin: (u16, u16),
     pub dimens: (u16, u16),
@@ -52,6 +241,13 @@ pub struct DrawArea {
 
 pub struct View {
     area: DrawArea,
+    too_small: bool,
+    tildes: bool,
+    laststatus: bool,
+    // The first line of the help shown instead of the bytes, if open
+    help: Option<usize>,
+    // Reused between frames to keep its allocation
+    frame: RefCell<Vec<u8>>,
     stdout: RawStdout,
     pub hex_view: HexView,
     pub status_view: StatusView,
@@ -59,14 +255,19 @@ pub struct View {
 
 impl View {
     pub fn new(stdout: RawStdout) -> View {
-        let hex_view = HexView::new(stdout.clone());
-        let status_view = StatusView::new(stdout.clone());
+        let hex_view = HexView::new();
+        let status_view = StatusView::new();
 
         View {
             area: DrawArea {
                 origin: (1, 1),
                 dimens: (16, 16),
             },
+            too_small: false,
+            tildes: true,
+            laststatus: true,
+            help: None,
+            frame: RefCell::new(Vec::new()),
             stdout,
             hex_view,
             status_view,
@@ -74,67 +275,325 @@ impl View {
     }
 
     pub fn draw(&self, model: &Model) -> IoResult<()> {
-        // limit scope of stdout here, because hex_view and status_view have their own reference.
-        {
-            let mut stdout = self.stdout.borrow_mut();
+        // Render the whole frame into memory first, so it reaches the terminal in a single write.
+        let mut frame = self.frame.borrow_mut();
+        frame.clear();
+
+        self.render(&mut *frame, model)?;
+
+        let mut stdout = self.stdout.borrow_mut();
+        stdout.write_all(&frame)?;
+        stdout.flush()
+    }
+
+    fn render(&self, frame: &mut dyn Write, model: &Model) -> IoResult<()> {
+        let frame = &mut ClipRight::new(frame, self.area.origin.0 + self.area.dimens.0);
+
+        // TODO: Better redraw only the dirty parts (ClearAll causes the flickering.)
+        write!(frame, "{}", ClearAll)?;
+
+        // Drawing the layout into a smaller terminal would only produce garbage.
+        if self.too_small {
+            let msg = "terminal too small";
+            let width = self.area.dimens.0 as usize;
+            write!(frame, "{}{}", Goto(1, 1), &msg[..min(msg.len(), width)])?;
+            return Ok(());
+        }
 
-            // TODO: Better redraw only the dirty parts (ClearAll causes the flickering.)
-            write!(stdout, "{}", ClearAll).unwrap();
+        if let Some(top) = self.help {
+            for (line, text) in HELP.iter().skip(top).take(self.help_height()).enumerate() {
+                write!(frame, "{}{}", Goto(1, 1 + line as u16), text)?;
+            }
+            self.status_view.draw(frame)?;
+            return Ok(());
+        }
 
-            write!(stdout, "{}", Fg(Red))?;
-            for line in 1..(self.area.dimens.1 - 1) {
-                write!(stdout, "{}~", Goto(1, line))?;
+        // Like Vim, only the lines below the last row are filled
+        if self.tildes {
+            let DrawArea {
+                origin: (x, y),
+                dimens: (_, h),
+            } = self.hex_view.area;
+            write!(frame, "{}", Fg(Red))?;
+            for line in (y + 1 + self.hex_view.rows_drawn(model))..(y + 1 + h) {
+                write!(frame, "{}~", Goto(x, line))?;
             }
-            write!(stdout, "{}", Fg(ColorReset))?;
+            write!(frame, "{}", Fg(ColorReset))?;
         }
 
-        self.hex_view.draw(model)?;
-        self.status_view.draw()?;
+        self.hex_view.draw(frame, model)?;
+        self.status_view.draw(frame)?;
 
         Ok(())
     }
 
+    // Replaces the bytes with the help, starting at its first line
+    pub fn show_help(&mut self, shown: bool) {
+        self.help = if shown { Some(0) } else { None };
+    }
+
+    pub fn scroll_help(&mut self, lines: isize) {
+        if let Some(top) = self.help {
+            let last = HELP.len().saturating_sub(self.help_height());
+            let top = if lines < 0 {
+                top.saturating_sub(lines.unsigned_abs())
+            } else {
+                top.saturating_add(lines as usize)
+            };
+            self.help = Some(min(top, last));
+        }
+    }
+
+    // Lines of help that fit above the status
+    pub fn help_height(&self) -> usize {
+        self.area.dimens.1.saturating_sub(self.status_height()) as usize
+    }
+
+    // Draws only the status, e.g. while the model is busy and can't be drawn
+    pub fn draw_status(&self) -> IoResult<()> {
+        if self.too_small {
+            return Ok(());
+        }
+
+        let mut stdout = self.stdout.borrow_mut();
+        self.status_view.draw(&mut *stdout)?;
+        stdout.flush()
+    }
+
+    // Wipes the terminal, including styles left over from stray output
+    pub fn clear(&self) -> IoResult<()> {
+        let mut stdout = self.stdout.borrow_mut();
+        write!(stdout, "{}{}{}", StyleReset, Fg(ColorReset), ClearAll)?;
+        stdout.flush()
+    }
+
+    // Whether to fill the unused lines with `~`
+    pub fn set_tildes(&mut self, tildes: bool) {
+        self.tildes = tildes;
+    }
+
+    // Writes a control sequence, e.g. for the terminal's clipboard
+    pub fn send(&self, sequence: &str) -> IoResult<()> {
+        let mut stdout = self.stdout.borrow_mut();
+        write!(stdout, "{}", sequence)?;
+        stdout.flush()
+    }
+
+    pub fn bell(&self) -> IoResult<()> {
+        let mut stdout = self.stdout.borrow_mut();
+        write!(stdout, "\x07")?;
+        stdout.flush()
+    }
+
     pub fn set_area(&mut self, area: DrawArea) {
         let DrawArea {
             origin: (x, y),
             dimens: (w, h),
         } = area;
 
+        let min_height = MIN_HEX_HEIGHT + self.status_height();
+        self.too_small = w < MIN_WIDTH || h < min_height;
+
+        if self.too_small {
+            // Only the notice is drawn, keep the real size for it.
+            self.area = DrawArea {
+                origin: (x, y),
+                dimens: (w, h),
+            };
+            return;
+        }
+
         // Set mimimum width/height to avoid overfow
-        let (w, h) = (max(w, 75), max(h, 4));
+        let (w, h) = (max(w, MIN_WIDTH), max(h, min_height));
 
         self.area = DrawArea {
             origin: (x, y),
             dimens: (w, h),
         };
 
+        // The hex view's index header is part of its area, but not of its height
+        let status_height = self.status_height();
         self.hex_view.set_area(DrawArea {
             origin: (x, y),
-            dimens: (w, h - 3),
+            dimens: (w, h - status_height - 1),
         });
 
         self.status_view.set_area(DrawArea {
-            origin: (x, y + h - 2),
-            dimens: (w, 2),
+            origin: (x, y + h - status_height),
+            dimens: (w, status_height),
         });
     }
+
+    // Without the last status line, only the line for messages and commands is left
+    pub fn set_laststatus(&mut self, laststatus: bool) {
+        self.laststatus = laststatus;
+
+        // Lay out again, the area is always the size of the terminal
+        let area = DrawArea {
+            origin: self.area.origin,
+            dimens: self.area.dimens,
+        };
+        self.set_area(area);
+    }
+
+    fn status_height(&self) -> u16 {
+        if self.laststatus {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+// How the character pane decodes the bytes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Ascii,
+    Latin1,
+    Cp437,
+    Utf8,
+}
+
+// The glyphs of code page 437 for 0x00 to 0x1f, 0x7f and 0x80 to 0xff, like a DOS screen shows them
+const CP437_LOW: &str = " ☺☻♥♦♣♠•◘○◙♂♀♪♫☼►◄↕‼¶§▬↨↑↓→←∟↔▲▼";
+const CP437_HIGH: &str = "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜ¢£¥₧ƒáíóúñÑªº¿⌐¬½¼¡«»\
+                          ░▒▓│┤╡╢╖╕╣║╗╝╜╛┐└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀\
+                          αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■ ";
+
+impl Encoding {
+    // One glyph per byte. A UTF-8 character is drawn at its first byte, with `·` for the rest of
+    // it, so the panes stay aligned, and bytes that don't decode are shown as `.`.
+    pub fn decode(self, bytes: &[u8]) -> Vec<char> {
+        match self {
+            Encoding::Ascii => bytes.iter().map(|byte| byte.to_printable()).collect(),
+            Encoding::Latin1 => bytes
+                .iter()
+                .map(|&byte| match byte {
+                    0x20..=0x7e | 0xa1..=0xac | 0xae..=0xff => char::from(byte),
+                    _ => '.',
+                })
+                .collect(),
+            Encoding::Cp437 => bytes
+                .iter()
+                .map(|&byte| match byte {
+                    0x00..=0x1f => CP437_LOW.chars().nth(byte as usize).unwrap(),
+                    0x7f => '⌂',
+                    0x80..=0xff => CP437_HIGH.chars().nth(byte as usize - 0x80).unwrap(),
+                    _ => char::from(byte),
+                })
+                .collect(),
+            Encoding::Utf8 => {
+                let mut chars = Vec::with_capacity(bytes.len());
+                let mut i = 0;
+                while i < bytes.len() {
+                    let len = match bytes[i] {
+                        0x00..=0x7f => 1,
+                        0xc2..=0xdf => 2,
+                        0xe0..=0xef => 3,
+                        0xf0..=0xf4 => 4,
+                        _ => 0,
+                    };
+                    let decoded = bytes
+                        .get(i..i + len)
+                        .and_then(|sequence| std::str::from_utf8(sequence).ok())
+                        .and_then(|sequence| sequence.chars().next());
+                    match decoded {
+                        Some(c) if !c.is_control() => {
+                            chars.push(c);
+                            chars.extend(repeat_n('·', len - 1));
+                            i += len;
+                        }
+                        _ => {
+                            chars.push('.');
+                            i += 1;
+                        }
+                    }
+                }
+                chars
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum CaretStyle {
+    Block,
+    Bar,
+    Underline,
+}
+
+// The glyph drawn over a column and the column it starts at, which is the one before for the
+// right half of a wide glyph
+fn glyph_at(cells: &[Option<char>], column: usize) -> Option<(usize, char)> {
+    match *cells.get(column)? {
+        Some(glyph) => Some((column, glyph)),
+        None => Some((column - 1, cells[column - 1]?)),
+    }
+}
+
+// Draws the hex part of a caret at the cell starting at `(x, y)`, `None` for no byte.
+fn write_caret(
+    stdout: &mut dyn Write,
+    style: CaretStyle,
+    (x, y): (u16, u16),
+    value: Option<String>,
+) -> IoResult<()> {
+    let value = value.unwrap_or_else(|| "  ".into());
+
+    match style {
+        CaretStyle::Block => write!(stdout, "{}{}{}{}", Goto(x, y), Invert, value, StyleReset),
+        CaretStyle::Bar => write!(stdout, "{}|", Goto(x - 1, y)),
+        CaretStyle::Underline => {
+            write!(stdout, "{}{}{}{}", Goto(x, y), Underline, value, StyleReset)
+        }
+    }
 }
 
 pub struct HexView {
     scroll_start: usize,
     area: DrawArea,
-    stdout: RawStdout,
+    caret_style: Option<CaretStyle>,
+    number_width: u16,
+    scrolloff: usize,
+    record: Option<usize>,
+    entropy: bool,
+    // Hex digits in upper case, like `AB` instead of `ab`
+    uppercase: bool,
+    cursorline: bool,
+    // Rows counted from the caret's row in the offset column, which keeps its absolute offset
+    relativenumber: bool,
+    // The offset column, left out to leave its width to the bytes
+    number: bool,
+    // A column of each row to shade, e.g. to line up fields
+    colorcolumn: Option<usize>,
+    encoding: Encoding,
+    // Lets wide glyphs spill into the cell of the next byte, instead of showing them as `.`
+    wrap: bool,
+    // Highlights the bytes equal to the caret's
+    matchbyte: bool,
 }
 
 impl HexView {
-    pub fn new(stdout: RawStdout) -> HexView {
+    pub fn new() -> HexView {
         HexView {
             scroll_start: 0,
             area: DrawArea {
                 origin: (1, 1),
                 dimens: (16, 16),
             },
-            stdout,
+            caret_style: None,
+            number_width: 0,
+            scrolloff: 0,
+            record: None,
+            entropy: false,
+            uppercase: false,
+            cursorline: false,
+            relativenumber: false,
+            number: true,
+            colorcolumn: None,
+            encoding: Encoding::Ascii,
+            wrap: false,
+            matchbyte: false,
         }
     }
 
@@ -142,34 +601,126 @@ impl HexView {
         self.area = area;
     }
 
-    pub fn draw(&self, model: &Model) -> IoResult<()> {
-        let mut stdout = self.stdout.borrow_mut();
+    pub fn set_uppercase(&mut self, uppercase: bool) {
+        self.uppercase = uppercase;
+    }
+
+    fn offset(&self, offset: usize, width: u16) -> String {
+        if self.uppercase {
+            format!("{:0width$X}", offset, width = width as usize)
+        } else {
+            format!("{:0width$x}", offset, width = width as usize)
+        }
+    }
+
+    fn hex(&self, byte: u8) -> String {
+        if self.uppercase {
+            format!("{:02X}", byte)
+        } else {
+            format!("{:02x}", byte)
+        }
+    }
 
-        let offset_width = offset_width(model.buffer.len());
+    // Overrides the per-mode caret style, `None` restores the default
+    pub fn set_caret_style(&mut self, style: Option<CaretStyle>) {
+        self.caret_style = style;
+    }
 
+    // The offset, hex and ASCII panes below the index header, for a buffer of `len` bytes
+    fn panes(&self, len: usize) -> (DrawArea, DrawArea, DrawArea) {
         let DrawArea {
             origin: (x, y),
-            dimens: (w, h),
+            dimens: (_, h),
         } = self.area;
         let offset_area = DrawArea {
             origin: (x, y + 1),
-            dimens: (offset_width as u16, h),
+            dimens: (
+                if self.number {
+                    max(offset_width(len), self.number_width)
+                } else {
+                    0
+                },
+                h,
+            ),
         };
+        // The offsets are followed by ": ", unless there are none
+        let separator = if self.number { 2 } else { 0 };
         let hex_area = DrawArea {
-            origin: (offset_area.origin.0 + offset_area.dimens.0 + 2, y + 1),
-            dimens: (16 * 2 + 15, h),
+            origin: (
+                offset_area.origin.0 + offset_area.dimens.0 + separator,
+                y + 1,
+            ),
+            dimens: (BYTES_PER_ROW as u16 * 3 - 1, h),
         };
         let ascii_area = DrawArea {
             origin: (hex_area.origin.0 + hex_area.dimens.0 + 2, y + 1),
-            dimens: (16, h),
+            dimens: (BYTES_PER_ROW as u16, h),
+        };
+
+        (offset_area, hex_area, ascii_area)
+    }
+
+    // Rows below the index header taken by bytes, or by the caret after the last byte
+    pub fn rows_drawn(&self, model: &Model) -> u16 {
+        if model.is_empty() {
+            return 0;
+        }
+
+        let end = max(model.len(), model.get_index() + 1);
+        let rows = (end.saturating_sub(self.scroll_start)).div_ceil(BYTES_PER_ROW);
+        min(rows, self.area.dimens.1 as usize) as u16
+    }
+
+    // The offset of the byte shown at a terminal cell of the hex or ASCII pane. `None` for
+    // anything else, e.g. offsets, the header, the gaps between bytes or beyond the end.
+    pub fn cell_to_offset(&self, col: u16, row: u16, len: usize) -> Option<usize> {
+        let (_, hex_area, ascii_area) = self.panes(len);
+
+        if row < hex_area.origin.1 || row >= hex_area.origin.1 + hex_area.dimens.1 {
+            return None;
+        }
+        let line = (row - hex_area.origin.1) as usize;
+
+        let no = if col >= hex_area.origin.0 && col < hex_area.origin.0 + hex_area.dimens.0 {
+            let rel = col - hex_area.origin.0;
+            if rel % 3 == 2 {
+                return None;
+            }
+            rel / 3
+        } else if col >= ascii_area.origin.0 && col < ascii_area.origin.0 + ascii_area.dimens.0 {
+            col - ascii_area.origin.0
+        } else {
+            return None;
+        };
+
+        let offset = self.scroll_start + line * BYTES_PER_ROW + no as usize;
+        if offset < len {
+            Some(offset)
+        } else {
+            None
+        }
+    }
+
+    pub fn draw(&self, stdout: &mut dyn Write, model: &Model) -> IoResult<()> {
+        let DrawArea {
+            origin: (x, y),
+            dimens: (w, h),
+        } = self.area;
+        let (offset_area, hex_area, ascii_area) = self.panes(model.len());
+        let offset_width = offset_area.dimens.0;
+        let entropy_area = DrawArea {
+            origin: (ascii_area.origin.0 + ascii_area.dimens.0 + 2, y + 1),
+            dimens: (1, h),
         };
+        // Left out if the terminal is too narrow for it
+        let entropy_visible = self.entropy && entropy_area.origin.0 < x + w;
 
-        if model.buffer.is_empty() {
+        if model.is_empty() {
             let msg = "empty file: go into insert mode and insert some bytes";
             write!(
                 stdout,
                 "{}{}",
-                Goto(w / 2 - (msg.len() as u16 / 2), h / 2),
+                Goto(max(x, (w / 2).saturating_sub(msg.len() as u16 / 2)), h / 2),
                 msg
             )
             .unwrap();
@@ -179,32 +730,56 @@ impl HexView {
 
         // Draw indices
         write!(stdout, "{}", Fg(Red))?;
+        let indices: String = (0..BYTES_PER_ROW).map(|i| format!("{:<3x}", i)).collect();
+        let indices = indices.trim_end();
         write!(
             stdout,
-            "{}0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f",
-            Goto(offset_width + 4, 1),
+            "{}{}",
+            Goto(hex_area.origin.0 + 1, 1),
+            if self.uppercase {
+                indices.to_uppercase()
+            } else {
+                indices.to_string()
+            }
         )?;
         write!(stdout, "{}", Fg(ColorReset))?;
 
-        for (line, chunk) in model.buffer[self.scroll_start..]
-            .chunks(16)
+        let visible_bytes = model.bytes(
+            self.scroll_start..min(model.len(), self.scroll_start + h as usize * BYTES_PER_ROW),
+        );
+
+        let caret_row = model.get_index() / BYTES_PER_ROW;
+
+        for (line, chunk) in visible_bytes
+            .chunks(BYTES_PER_ROW)
             .take(h as usize)
             .enumerate()
         {
-            let offset = line * 16;
+            let offset = line * BYTES_PER_ROW;
+            let row = (offset + self.scroll_start) / BYTES_PER_ROW;
             let line = line as u16;
 
             // Draw offsets
-            write!(
-                stdout,
-                "{}{}{:0width$x}: {}",
-                Goto(offset_area.origin.0, offset_area.origin.1 + line),
-                Fg(Red),
-                offset + self.scroll_start,
-                Fg(ColorReset),
-                width = offset_width as usize
-            )
-            .unwrap();
+            if self.number {
+                let number = if self.relativenumber && row != caret_row {
+                    format!(
+                        "{:>width$}",
+                        max(row, caret_row) - min(row, caret_row),
+                        width = offset_width as usize
+                    )
+                } else {
+                    self.offset(offset + self.scroll_start, offset_width)
+                };
+                write!(
+                    stdout,
+                    "{}{}{}: {}",
+                    Goto(offset_area.origin.0, offset_area.origin.1 + line),
+                    Fg(Red),
+                    number,
+                    Fg(ColorReset),
+                )
+                .unwrap();
+            }
 
             // Draw hex values
             write!(
@@ -214,7 +789,27 @@ impl HexView {
             )
             .unwrap();
             for byte in chunk {
-                write!(stdout, "{:02x} ", byte).unwrap();
+                write!(stdout, "{} ", self.hex(*byte)).unwrap();
+            }
+
+            // Draw record separators in the gap before each record's first byte
+            if let Some(record) = self.record {
+                let row_start = self.scroll_start + offset;
+                for column in 0..chunk.len() {
+                    let index = row_start + column;
+                    if index != 0 && index.is_multiple_of(record) {
+                        write!(
+                            stdout,
+                            "{}{}|{}",
+                            Goto(
+                                hex_area.origin.0 + column as u16 * 3 - 1,
+                                hex_area.origin.1 + line
+                            ),
+                            Faint,
+                            StyleReset
+                        )?;
+                    }
+                }
             }
 
             // Draw ascii values
@@ -224,206 +819,423 @@ impl HexView {
                 Goto(ascii_area.origin.0, ascii_area.origin.1 + line)
             )
             .unwrap();
-            for byte in chunk {
-                write!(stdout, "{}", byte.to_printable()).unwrap();
+            for glyph in self.cells(chunk).into_iter().flatten() {
+                write!(stdout, "{}", glyph).unwrap();
             }
-        }
 
-        // Draw Caret
-        match model.caret {
-            Caret::Index(index) => {
-                let index = usize::from(index);
+            // Draw entropy, relative to the maximum of the row, so partial rows compare
+            if entropy_visible {
+                let max = (chunk.len() as f64).log2();
+                let level = if max > 0.0 { entropy(chunk) / max } else { 0.0 };
+                let shade = [' ', '░', '▒', '▓', '█'][(level * 4.0).round() as usize];
                 write!(
                     stdout,
-                    "{}|",
-                    Goto(
-                        hex_area.origin.0 + ((index % 16) as u16) * 3 - 1,
-                        hex_area.origin.1 + ((index - self.scroll_start) / 16) as u16
-                    ),
-                )
-                .unwrap();
+                    "{}{}",
+                    Goto(entropy_area.origin.0, entropy_area.origin.1 + line),
+                    shade
+                )?;
+            }
+        }
 
-                let value = if index < model.buffer.len() {
-                    model.buffer[index].to_printable()
-                } else {
-                    ' '
-                };
+        // Shade the column's hex and ASCII cells, in all rows long enough to have it
+        if let Some(column) = self.colorcolumn {
+            for (line, chunk) in visible_bytes
+                .chunks(BYTES_PER_ROW)
+                .take(h as usize)
+                .enumerate()
+            {
+                if let (Some(&byte), Some((at, glyph))) =
+                    (chunk.get(column), glyph_at(&self.cells(chunk), column))
+                {
+                    let line = line as u16;
+                    write!(
+                        stdout,
+                        "{}{}{}{}{}{}",
+                        Bg(AnsiValue::grayscale(5)),
+                        Goto(
+                            hex_area.origin.0 + column as u16 * 3,
+                            hex_area.origin.1 + line
+                        ),
+                        self.hex(byte),
+                        Goto(ascii_area.origin.0 + at as u16, ascii_area.origin.1 + line),
+                        glyph,
+                        Bg(ColorReset)
+                    )?;
+                }
+            }
+        }
 
-                write!(
+        // Highlight the other visible bytes equal to the caret's
+        let caret_byte = model.byte_at(model.get_index());
+        if let Some(current) = caret_byte.filter(|_| self.matchbyte) {
+            for (line, chunk) in visible_bytes
+                .chunks(BYTES_PER_ROW)
+                .take(h as usize)
+                .enumerate()
+            {
+                let cells = self.cells(chunk);
+                for (column, &byte) in chunk.iter().enumerate() {
+                    let index = self.scroll_start + line * BYTES_PER_ROW + column;
+                    if byte != current || index == model.get_index() {
+                        continue;
+                    }
+
+                    // Safe-from-panic: There is a cell for each byte of the row
+                    let (at, glyph) = glyph_at(&cells, column).unwrap();
+                    let line = line as u16;
+                    write!(
+                        stdout,
+                        "{}{}{}{}{}{}",
+                        Bg(AnsiValue::grayscale(3)),
+                        Goto(
+                            hex_area.origin.0 + column as u16 * 3,
+                            hex_area.origin.1 + line
+                        ),
+                        self.hex(byte),
+                        Goto(ascii_area.origin.0 + at as u16, ascii_area.origin.1 + line),
+                        glyph,
+                        Bg(ColorReset)
+                    )?;
+                }
+            }
+        }
+
+        // Underline the bytes with notes
+        let visible = self.scroll_start..self.scroll_start + visible_bytes.len();
+        for &offset in model.notes.keys().filter(|offset| visible.contains(offset)) {
+            let column = (offset % BYTES_PER_ROW) as u16;
+            let line = ((offset - self.scroll_start) / BYTES_PER_ROW) as u16;
+            write!(
+                stdout,
+                "{}{}{}{}{}{}",
+                Goto(hex_area.origin.0 + column * 3, hex_area.origin.1 + line),
+                Fg(Yellow),
+                Underline,
+                self.hex(visible_bytes[offset - self.scroll_start]),
+                NoUnderline,
+                Fg(ColorReset)
+            )?;
+        }
+
+        // Underlay the caret's row dimly, so the caret drawn on top of it still stands out
+        let row_start = model.get_index() - model.get_index() % BYTES_PER_ROW;
+        if self.cursorline
+            && row_start >= self.scroll_start
+            && (row_start - self.scroll_start) / BYTES_PER_ROW < h as usize
+        {
+            let line = ((row_start - self.scroll_start) / BYTES_PER_ROW) as u16;
+            let row = model.bytes(row_start..min(row_start + BYTES_PER_ROW, model.len()));
+
+            write!(
+                stdout,
+                "{}{}{}",
+                Goto(offset_area.origin.0, offset_area.origin.1 + line),
+                Faint,
+                Invert
+            )?;
+            if self.number {
+                write!(stdout, "{}:", self.offset(row_start, offset_width))?;
+            }
+            write!(
+                stdout,
+                "{}{}",
+                Goto(hex_area.origin.0, hex_area.origin.1 + line),
+                row.iter()
+                    .map(|&byte| self.hex(byte))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            )?;
+            write!(
+                stdout,
+                "{}{}{}",
+                Goto(ascii_area.origin.0, ascii_area.origin.1 + line),
+                self.cells(&row).into_iter().flatten().collect::<String>(),
+                StyleReset
+            )?;
+        }
+
+        // Draw Caret
+        match model.caret {
+            Caret::Index(index) => {
+                let index = index.value();
+                write_caret(
                     stdout,
-                    "{}{}{}{}",
-                    Goto(
-                        ascii_area.origin.0 + ((index % 16) as u16),
-                        ascii_area.origin.1 + ((index - self.scroll_start) / 16) as u16
+                    self.caret_style.unwrap_or(CaretStyle::Bar),
+                    (
+                        hex_area.origin.0 + ((index % BYTES_PER_ROW) as u16) * 3,
+                        hex_area.origin.1 + ((index - self.scroll_start) / BYTES_PER_ROW) as u16,
                     ),
-                    Underline,
-                    value,
-                    StyleReset
-                )
-                .unwrap();
+                    model.byte_at(index).map(|byte| self.hex(byte)),
+                )?;
+                self.underline_glyph(stdout, model, ascii_area.origin, index)?;
             }
+            // Past the last byte with `virtualedit`, which leaves an empty cell
             Caret::Offset(index) => {
-                let index = usize::from(index);
-                let byte = model.buffer[index];
+                let index = index.value();
 
-                write!(
-                    stdout,
-                    "{}{}{:02x}{}",
-                    Goto(
-                        hex_area.origin.0 + ((index % 16) as u16) * 3,
-                        hex_area.origin.1 + ((index - self.scroll_start) / 16) as u16
-                    ),
-                    Invert,
-                    byte,
-                    StyleReset
-                )
-                .unwrap();
-                write!(
+                write_caret(
                     stdout,
-                    "{}{}{}{}",
-                    Goto(
-                        ascii_area.origin.0 + ((index % 16) as u16),
-                        ascii_area.origin.1 + ((index - self.scroll_start) / 16) as u16
+                    self.caret_style.unwrap_or(CaretStyle::Block),
+                    (
+                        hex_area.origin.0 + ((index % BYTES_PER_ROW) as u16) * 3,
+                        hex_area.origin.1 + ((index - self.scroll_start) / BYTES_PER_ROW) as u16,
                     ),
-                    Underline,
-                    byte.to_printable(),
-                    StyleReset
-                )
-                .unwrap();
+                    model.byte_at(index).map(|byte| self.hex(byte)),
+                )?;
+                self.underline_glyph(stdout, model, ascii_area.origin, index)?;
             }
             Caret::Replace(index) => {
-                let index = usize::from(index);
-                let byte = model.buffer[index];
+                let index = index.value();
 
-                write!(
-                    stdout,
-                    "{}{}{:02x}{}",
-                    Goto(
-                        hex_area.origin.0 + ((index % 16) as u16) * 3,
-                        hex_area.origin.1 + ((index - self.scroll_start) / 16) as u16
-                    ),
-                    Underline,
-                    byte,
-                    StyleReset
-                )
-                .unwrap();
                 write!(
                     stdout,
                     "{}{}{}{}",
                     Goto(
-                        ascii_area.origin.0 + ((index % 16) as u16),
-                        ascii_area.origin.1 + ((index - self.scroll_start) / 16) as u16
+                        hex_area.origin.0 + ((index % BYTES_PER_ROW) as u16) * 3,
+                        hex_area.origin.1 + ((index - self.scroll_start) / BYTES_PER_ROW) as u16
                     ),
                     Underline,
-                    byte.to_printable(),
+                    model
+                        .byte_at(index)
+                        .map_or("  ".into(), |byte| self.hex(byte)),
                     StyleReset
                 )
                 .unwrap();
+                self.underline_glyph(stdout, model, ascii_area.origin, index)?;
             }
             Caret::Visual(start, end) => {
-                let start = usize::from(start);
-                let end = usize::from(end);
-                let rel_start = (start.saturating_sub(self.scroll_start)) as u16;
-                let rel_end = (end.saturating_sub(self.scroll_start)) as u16;
+                let start = start.value();
+                let end = end.value();
 
-                let lines = range_to_marker(rel_start, rel_end);
+                // Only the visible part of the selection is highlighted
+                let visible_end = self.scroll_start + (h as usize) * BYTES_PER_ROW;
+                let (first, last) = (min(start, end), max(start, end));
 
-                for &(line, s, e) in lines.iter().take(h as usize) {
-                    for no in s..e {
-                        let byte =
-                            model.buffer[no as usize + line as usize * 16 + self.scroll_start];
-                        write!(
-                            stdout,
-                            "{}{}{:02x} {}",
-                            Goto(hex_area.origin.0 + no * 3, hex_area.origin.1 + line),
-                            Invert,
-                            byte,
-                            StyleReset
-                        )
-                        .unwrap();
-                        write!(
-                            stdout,
-                            "{}{}{}{}",
-                            Goto(ascii_area.origin.0 + no, ascii_area.origin.1 + line),
-                            Underline,
-                            byte.to_printable(),
-                            StyleReset
-                        )
-                        .unwrap();
-                    }
-                    let byte = model.buffer[e as usize + line as usize * 16 + self.scroll_start];
-                    write!(
-                        stdout,
-                        "{}{}{:02x}{}",
-                        Goto(hex_area.origin.0 + e * 3, hex_area.origin.1 + line),
-                        Invert,
-                        byte,
-                        StyleReset
-                    )
-                    .unwrap();
+                if last >= self.scroll_start && first < visible_end {
+                    let rel_start = (max(first, self.scroll_start) - self.scroll_start) as u16;
+                    let rel_end = (min(last, visible_end - 1) - self.scroll_start) as u16;
+
+                    let lines = range_to_marker(rel_start, rel_end);
+
+                    for &(line, s, e) in lines.iter().take(h as usize) {
+                        let row_start = line as usize * BYTES_PER_ROW + self.scroll_start;
+
+                        for no in s..e {
+                            // The selection may reach beyond a partial final row
+                            let byte = match model.byte_at(row_start + no as usize) {
+                                Some(byte) => byte,
+                                None => break,
+                            };
+                            write!(
+                                stdout,
+                                "{}{}{} {}",
+                                Goto(hex_area.origin.0 + no * 3, hex_area.origin.1 + line),
+                                Invert,
+                                self.hex(byte),
+                                StyleReset
+                            )
+                            .unwrap();
+                            self.underline_glyph(
+                                stdout,
+                                model,
+                                ascii_area.origin,
+                                row_start + no as usize,
+                            )?;
+                        }
+
+                        if let Some(byte) = model.byte_at(row_start + e as usize) {
+                            write!(
+                                stdout,
+                                "{}{}{}{}",
+                                Goto(hex_area.origin.0 + e * 3, hex_area.origin.1 + line),
+                                Invert,
+                                self.hex(byte),
+                                StyleReset
+                            )
+                            .unwrap();
+                            self.underline_glyph(
+                                stdout,
+                                model,
+                                ascii_area.origin,
+                                row_start + e as usize,
+                            )?;
+                        }
+                    }
+                }
+
+                if (self.scroll_start..visible_end).contains(&end) && end < model.len() {
+                    // Safe-from-panic: Checked to be within the buffer just above
+                    let byte = model.byte_at(end).unwrap();
                     write!(
                         stdout,
-                        "{}{}{}{}",
-                        Goto(ascii_area.origin.0 + e, ascii_area.origin.1 + line),
-                        Underline,
-                        byte.to_printable(),
+                        "{}{}{}{}{}",
+                        Goto(
+                            hex_area.origin.0 + ((end % BYTES_PER_ROW) as u16) * 3,
+                            hex_area.origin.1 + ((end - self.scroll_start) / BYTES_PER_ROW) as u16
+                        ),
+                        Invert,
+                        Bold,
+                        self.hex(byte),
                         StyleReset
                     )
                     .unwrap();
                 }
-
-                let byte = model.buffer[end];
-                write!(
-                    stdout,
-                    "{}{}{}{:02x}{}",
-                    Goto(
-                        hex_area.origin.0 + ((end % 16) as u16) * 3,
-                        hex_area.origin.1 + ((end - self.scroll_start) / 16) as u16
-                    ),
-                    Invert,
-                    Bold,
-                    byte,
-                    StyleReset
-                )
-                .unwrap();
             }
         }
 
         Ok(())
     }
 
+    // Pins the offset column to at least `width` digits, so the grid doesn't shift while editing
+    pub fn set_number_width(&mut self, width: u16) {
+        self.number_width = width;
+    }
+
+    // Rows of context kept above and below the caret when scrolling
+    pub fn set_scrolloff(&mut self, rows: usize) {
+        self.scrolloff = rows;
+    }
+
+    pub fn set_colorcolumn(&mut self, column: Option<usize>) {
+        self.colorcolumn = column;
+    }
+
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+    }
+
+    pub fn set_matchbyte(&mut self, matchbyte: bool) {
+        self.matchbyte = matchbyte;
+    }
+
+    // The glyphs of a row, one cell per byte. `None` is the right half of the wide glyph before.
+    fn cells(&self, row: &[u8]) -> Vec<Option<char>> {
+        let mut cells: Vec<_> = self.encoding.decode(row).into_iter().map(Some).collect();
+
+        // A wide character has more than two bytes in UTF-8, so the next cell is always its own
+        for column in 0..cells.len() {
+            if cells[column].is_some_and(|c| UnicodeWidthChar::width(c) == Some(2)) {
+                if !self.wrap {
+                    cells[column] = Some('.');
+                } else if let Some(next) = cells.get_mut(column + 1) {
+                    *next = None;
+                }
+            }
+        }
+
+        cells
+    }
+
+    // Underlines the glyph of the byte at `index`, decoded along with its row, as a UTF-8
+    // character may start before it. A space marks the end of the buffer.
+    fn underline_glyph(
+        &self,
+        stdout: &mut dyn Write,
+        model: &Model,
+        origin: (u16, u16),
+        index: usize,
+    ) -> IoResult<()> {
+        let row_start = index - index % BYTES_PER_ROW;
+        let row = model.bytes(row_start..min(row_start + BYTES_PER_ROW, model.len()));
+        let (column, glyph) =
+            glyph_at(&self.cells(&row), index - row_start).unwrap_or((index - row_start, ' '));
+
+        write!(
+            stdout,
+            "{}{}{}{}",
+            Goto(
+                origin.0 + column as u16,
+                origin.1 + ((row_start - self.scroll_start) / BYTES_PER_ROW) as u16
+            ),
+            Underline,
+            glyph,
+            StyleReset
+        )
+    }
+
+    pub fn set_cursorline(&mut self, cursorline: bool) {
+        self.cursorline = cursorline;
+    }
+
+    pub fn set_relativenumber(&mut self, relativenumber: bool) {
+        self.relativenumber = relativenumber;
+    }
+
+    pub fn set_number(&mut self, number: bool) {
+        self.number = number;
+    }
+
+    // Whether to shade each row by the entropy of its bytes
+    pub fn set_entropy(&mut self, entropy: bool) {
+        self.entropy = entropy;
+    }
+
+    // Size of fixed-size records to separate, `None` draws no separators
+    pub fn set_record(&mut self, record: Option<usize>) {
+        self.record = record;
+    }
+
+    pub fn get_record(&self) -> Option<usize> {
+        self.record
+    }
+
+    pub fn get_scroll_start(&self) -> usize {
+        self.scroll_start
+    }
+
+    pub fn set_scroll_start(&mut self, scroll_start: usize) {
+        self.scroll_start = scroll_start;
+    }
+
     pub fn scroll_to(&mut self, index: usize) {
         let DrawArea {
             origin: (_, _),
             dimens: (_, h),
         } = self.area;
 
-        let start = self.scroll_start / 16;
-        let index = index / 16;
+        let start = self.scroll_start / BYTES_PER_ROW;
+        let index = index / BYTES_PER_ROW;
 
-        self.scroll_start = move_window(start, h as usize, index).unwrap() * 16;
+        self.scroll_start =
+            move_window(start, h as usize, index, self.scrolloff).unwrap() * BYTES_PER_ROW;
     }
 }
 
+// Columns left for `:set showcmd` in front of the offset
+const SHOWCMD_WIDTH: usize = 10;
+
 pub struct StatusView {
     pub head: String,
     pub body: String,
     pub index: usize,
+    pub byte: Option<u8>,
+    // The note on the byte under the caret, shown unless there is a message
+    pub note: Option<String>,
+    // Keys of a command being typed, `None` leaves out their column
+    pub pending: Option<String>,
+    // Decodes the byte as an `i8`
+    pub signed: bool,
     pub area: DrawArea,
-    stdout: RawStdout,
 }
 
 impl StatusView {
-    pub fn new(stdout: RawStdout) -> StatusView {
+    pub fn new() -> StatusView {
         StatusView {
             head: "".into(),
             body: "".into(),
             index: 0,
+            byte: None,
+            note: None,
+            pending: None,
+            signed: false,
             area: DrawArea {
                 origin: (1, 1),
                 dimens: (16, 2),
             },
-            stdout,
         }
     }
 
@@ -439,53 +1251,114 @@ impl StatusView {
         self.index = index;
     }
 
+    // The byte under the caret, decoded at the right of the status line
+    pub fn set_byte(&mut self, byte: Option<u8>) {
+        self.byte = byte;
+    }
+
     pub fn set_area(&mut self, area: DrawArea) {
         self.area = area;
     }
 
-    pub fn draw(&self) -> IoResult<()> {
-        let mut stdout = self.stdout.borrow_mut();
-
+    pub fn draw(&self, stdout: &mut dyn Write) -> IoResult<()> {
         let DrawArea {
             origin: (x, y),
-            dimens: (w, _),
+            dimens: (w, h),
         } = self.area;
 
-        write!(
-            stdout,
-            "{}{}{}{:<pad$}{}",
-            Goto(x, y),
-            ClearCurrentLine,
-            Invert,
-            self.head,
-            NoInvert,
-            pad = (w as usize),
-        )?;
+        // The head is left out when there is only a single line
+        if h > 1 {
+            write!(
+                stdout,
+                "{}{}{}{:<pad$}{}",
+                Goto(x, y),
+                ClearCurrentLine,
+                Invert,
+                self.head,
+                NoInvert,
+                pad = (w as usize),
+            )?;
+        }
+        let body_y = y + h.saturating_sub(1);
+
+        // The offset and the decoded byte are right-aligned, the body gets the rest of the line
+        let mut readout = format!("0x{:x} ({})", self.index, self.index);
+        if let Some(byte) = self.byte {
+            readout.push_str("  ");
+            readout.push_str(&describe_byte(byte, self.signed));
+        }
+        let readout_len = readout.chars().count();
+
+        let pending_len = if self.pending.is_some() {
+            SHOWCMD_WIDTH + 1
+        } else {
+            0
+        };
+
+        let body = match self.note {
+            Some(ref note) if self.body.is_empty() => format!("note: {}", note),
+            _ => self.body.clone(),
+        };
         write!(
             stdout,
             "{}{}{}",
-            Goto(x, y + 1),
+            Goto(x, body_y),
             ClearCurrentLine,
-            self.body
+            clip(
+                &body,
+                (w as usize).saturating_sub(readout_len + 1 + pending_len)
+            )
         )?;
-        let offset_msg = format!("0x{:x} ({})", self.index, self.index);
+
+        // Like Vim, the latest keys are kept if they don't fit
+        if let Some(ref pending) = self.pending {
+            let skip = pending.chars().count().saturating_sub(SHOWCMD_WIDTH);
+            let pending: String = pending.chars().skip(skip).collect();
+            let pending_x = (x + w).saturating_sub((readout_len + pending_len) as u16);
+            write!(stdout, "{}{}", Goto(max(x, pending_x), body_y), pending)?;
+        }
+
         write!(
             stdout,
             "{}{}",
-            Goto(x + w / 2 - (offset_msg.len() as u16 / 2), y + 1),
-            offset_msg
+            Goto(max(x, (x + w).saturating_sub(readout_len as u16)), body_y),
+            readout
         )?;
 
-        stdout.flush()?;
-
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::rc::Rc;
+
     use super::*;
 
+    // What a headless view has drawn so far
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Output {
+        fn screen(&self) -> String {
+            String::from_utf8_lossy(&self.0.borrow()).into_owned()
+        }
+
+        fn clear(&self) {
+            self.0.borrow_mut().clear();
+        }
+    }
+
+    // An 80x24 view drawing into a buffer
+    fn headless() -> (View, Output) {
+        let output = Output(Rc::new(RefCell::new(Vec::new())));
+        let mut view = View::new(output.0.clone());
+        view.set_area(DrawArea {
+            origin: (1, 1),
+            dimens: (80, 24),
+        });
+        (view, output)
+    }
+
     #[test]
     fn test_chunks_indices() {
         assert_eq!(
@@ -513,9 +1386,686 @@ mod test {
         assert_eq!(chunks_indices(13, 19, 6), vec![(13, 18), (19, 19)]);
     }
 
+    #[test]
+    fn test_draw_too_small() {
+        use crate::UsizeMax;
+
+        let (mut view, output) = headless();
+
+        let mut model = Model::new();
+        model.buffer = (0..=255).collect();
+        model.caret = Caret::Visual(UsizeMax::new(3, 255), UsizeMax::new(200, 255));
+
+        for &size in &[(0, 0), (1, 1), (10, 2), (39, 40), (80, 3)] {
+            output.clear();
+            view.set_area(DrawArea {
+                origin: (1, 1),
+                dimens: size,
+            });
+            view.draw(&model).unwrap();
+
+            let output = output.screen();
+            let notice = &"terminal too small"[..min(18, size.0 as usize)];
+            assert!(output.contains(notice));
+            assert!(!output.contains("ff"));
+        }
+    }
+
+    #[test]
+    fn test_draw_narrow() {
+        use crate::UsizeMax;
+
+        let (mut view, output) = headless();
+        view.set_area(DrawArea {
+            origin: (1, 1),
+            dimens: (60, 24),
+        });
+        view.hex_view.set_entropy(true);
+
+        let mut model = Model::new();
+        model.buffer = (0..=255).collect();
+        model.caret = Caret::Visual(UsizeMax::new(0, 255), UsizeMax::new(40, 255));
+        view.draw(&model).unwrap();
+
+        // Replay the output on a grid that wraps like a terminal would
+        let output = output.screen();
+        let (mut col, mut escape, mut wrapped) = (1, String::new(), false);
+        for c in output.chars() {
+            if !escape.is_empty() || c == '\x1b' {
+                escape.push(c);
+                if c.is_ascii_alphabetic() {
+                    if let Some(params) = escape
+                        .strip_prefix("\x1b[")
+                        .and_then(|e| e.strip_suffix('H'))
+                    {
+                        col = params.split(';').nth(1).unwrap().parse().unwrap();
+                    }
+                    escape.clear();
+                }
+            } else {
+                wrapped |= col > 60;
+                col += 1;
+            }
+        }
+        assert!(!wrapped);
+
+        // The ASCII pane starts at 55 with 3 digit offsets, so only 6 of its columns remain
+        let ascii = output.find(&format!("{}", Goto(55, 2))).unwrap() + 7;
+        assert!(output[ascii..].starts_with("......\x1b"));
+        assert!(output.contains("0x0 (0)"));
+    }
+
+    #[test]
+    fn test_help() {
+        let (mut view, output) = headless();
+        view.set_area(DrawArea {
+            origin: (1, 1),
+            dimens: (80, 12),
+        });
+
+        let mut model = Model::new();
+        model.buffer = vec![0xab; 16].into();
+
+        view.show_help(true);
+        view.draw(&model).unwrap();
+        let screen = output.screen();
+        assert!(screen.contains(HELP[0]));
+        assert!(screen.contains(HELP[9]));
+        assert!(!screen.contains(HELP[10]));
+        assert!(!screen.contains("ab ab"));
+
+        // Scrolling stops at the last page
+        view.scroll_help(-1);
+        assert_eq!(view.help, Some(0));
+        view.scroll_help(1000);
+        assert_eq!(view.help, Some(HELP.len() - 10));
+
+        output.clear();
+        view.show_help(false);
+        view.draw(&model).unwrap();
+        assert!(output.screen().contains("ab ab"));
+    }
+
+    #[test]
+    fn test_clip_right() {
+        let mut output = Vec::new();
+        {
+            let mut clipped = ClipRight::new(&mut output, 6);
+            write!(clipped, "{}abcdefgh{}", Goto(3, 1), Invert).unwrap();
+            write!(clipped, "{}äöü░▒▓", Goto(4, 2)).unwrap();
+            write!(clipped, "{}xy", Goto(1, 3)).unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "{}abc{}{}äö{}xy",
+                Goto(3, 1),
+                Invert,
+                Goto(4, 2),
+                Goto(1, 3)
+            )
+        );
+    }
+
+    #[test]
+    fn test_draw_visual_partial_row() {
+        use crate::UsizeMax;
+
+        let (view, output) = headless();
+
+        let mut model = Model::new();
+        model.buffer = (0..20).collect();
+
+        // Selection ending on the last byte of a partial final row...
+        let forward = Caret::Visual(UsizeMax::new(3, 19), UsizeMax::new(19, 19));
+        // ...backwards...
+        let backward = Caret::Visual(UsizeMax::new(19, 19), UsizeMax::new(0, 19));
+        // ...and with a stale maximum reaching past the buffer.
+        let stale = Caret::Visual(UsizeMax::new(5, 40), UsizeMax::new(40, 40));
+
+        for (caret, first) in [(forward, 3), (backward, 0), (stale, 5)] {
+            output.clear();
+            model.caret = caret;
+            view.draw(&model).unwrap();
+
+            let screen = output.screen();
+            for byte in 0..0x20 {
+                let highlighted = screen.contains(&format!("{}{:02x}", Invert, byte));
+                assert_eq!(highlighted, (first..0x14).contains(&byte), "{:02x}", byte);
+            }
+        }
+    }
+
+    #[test]
+    fn test_draw_visual_single_cell() {
+        use crate::UsizeMax;
+
+        let (view, output) = headless();
+
+        let mut model = Model::new();
+        model.buffer = (0..32).collect();
+        model.caret = Caret::Visual(UsizeMax::new(5, 31), UsizeMax::new(5, 31));
+        view.draw(&model).unwrap();
+
+        let output = output.screen();
+        // Exactly one highlighted cell plus the bold caret on top of it
+        assert_eq!(output.matches(&format!("{}05", Invert)).count(), 1);
+        assert_eq!(output.matches(&format!("{}{}05", Invert, Bold)).count(), 1);
+        assert!(!output.contains(&format!("{}04", Invert)));
+        assert!(!output.contains(&format!("{}06", Invert)));
+    }
+
+    #[test]
+    fn test_draw_single_write() {
+        // Counts the writes reaching the terminal
+        struct Writes(usize);
+
+        impl Write for Writes {
+            fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+                self.0 += 1;
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> IoResult<()> {
+                Ok(())
+            }
+        }
+
+        let output = Rc::new(RefCell::new(Writes(0)));
+        let mut view = View::new(output.clone());
+        view.set_area(DrawArea {
+            origin: (1, 1),
+            dimens: (80, 24),
+        });
+
+        let mut model = Model::new();
+        model.buffer = (0..=255).collect();
+        view.draw(&model).unwrap();
+        assert_eq!(output.borrow().0, 1);
+    }
+
+    #[test]
+    fn test_draw_record_separators() {
+        let (mut view, output) = headless();
+        view.hex_view.set_record(Some(6));
+
+        let mut model = Model::new();
+        model.buffer = vec![0; 32].into();
+        view.draw(&model).unwrap();
+
+        // Before the bytes at 6, 12, 18, 24 and 30
+        let output = output.screen();
+        assert_eq!(output.matches(&format!("{}|", Faint)).count(), 5);
+    }
+
+    #[test]
+    fn test_draw_entropy() {
+        let mut model = Model::new();
+        model.buffer = std::iter::repeat_n(0, 16).chain(0..16).collect();
+
+        // The column ends at 78 with 8 digit offsets
+        for &(width, shown) in &[(80, true), (75, false)] {
+            let (mut view, output) = headless();
+            view.set_area(DrawArea {
+                origin: (1, 1),
+                dimens: (width, 24),
+            });
+            view.hex_view.set_number_width(8);
+            view.hex_view.set_entropy(true);
+            view.draw(&model).unwrap();
+
+            let output = output.screen();
+            assert_eq!(output.matches('█').count(), shown as usize);
+        }
+    }
+
+    #[test]
+    fn test_clip() {
+        assert_eq!(clip("abc", 3), "abc");
+        assert_eq!(clip("abcd", 3), format!("ab…{}", StyleReset));
+        assert_eq!(clip("abcd", 0), "");
+
+        let bold = format!("{}-- Insert --{}", Bold, StyleReset);
+        assert_eq!(visible_len(&bold), 12);
+        assert_eq!(clip(&bold, 12), bold);
+        assert_eq!(clip(&bold, 4), format!("{}-- …{}", Bold, StyleReset));
+    }
+
+    #[test]
+    fn test_draw_long_status() {
+        let (mut view, output) = headless();
+        view.status_view.set_body(&"x".repeat(200));
+
+        let model = Model::new();
+        view.draw(&model).unwrap();
+
+        // Leaving room for " 0x0 (0)"
+        let output = output.screen();
+        assert!(output.contains(&format!("{}…", "x".repeat(71))));
+        assert!(!output.contains(&"x".repeat(72)));
+    }
+
+    #[test]
+    fn test_draw_status_readout() {
+        let (mut view, output) = headless();
+
+        // 39 characters of readout and a space leave 40 for the body
+        view.status_view.set_body(&"x".repeat(60));
+        view.status_view.set_index(0x41);
+        view.status_view.set_byte(Some(0x41));
+        view.draw(&Model::new()).unwrap();
+
+        let output = output.screen();
+        let readout = "0x41 (65)  0x41 65 0o101 0b01000001 'A'";
+        assert!(output.contains(&format!(
+            "{}{}",
+            Goto(81 - readout.len() as u16, 24),
+            readout
+        )));
+        assert!(output.contains(&format!("{}…", "x".repeat(39))));
+        assert!(!output.contains(&"x".repeat(40)));
+    }
+
+    #[test]
+    fn test_laststatus() {
+        let (mut view, output) = headless();
+        view.set_area(DrawArea {
+            origin: (1, 1),
+            dimens: (80, 3),
+        });
+        assert!(view.too_small);
+
+        // The head line is gone, the hex view gets its row
+        view.set_laststatus(false);
+        assert!(!view.too_small);
+        view.set_area(DrawArea {
+            origin: (1, 1),
+            dimens: (80, 24),
+        });
+        assert_eq!(view.hex_view.area.dimens.1, 22);
+        assert_eq!(view.status_view.area.origin.1, 24);
+
+        let mut model = Model::new();
+        model.buffer = vec![0; 16 * 30].into();
+        model.path = "file.bin".into();
+        view.status_view.set_head(&model.path);
+        view.draw(&model).unwrap();
+
+        let output = output.screen();
+        assert!(!output.contains("file.bin"));
+        // The last row of the grid takes the old head line
+        assert!(output.contains(&format!("{}{}150: ", Goto(1, 23), Fg(Red))));
+
+        view.set_laststatus(true);
+        assert_eq!(view.hex_view.area.dimens.1, 21);
+    }
+
+    #[test]
+    fn test_draw_uppercase() {
+        use crate::UsizeMax;
+
+        let (mut view, output) = headless();
+
+        let mut model = Model::new();
+        model.buffer = vec![0xab; 0xcd].into();
+        model.caret = Caret::Offset(UsizeMax::new(0, 0xcc));
+
+        view.hex_view.set_uppercase(true);
+        view.draw(&model).unwrap();
+        let screen = output.screen();
+        assert!(screen.contains("AB AB"));
+        assert!(screen.contains("C0: "));
+        assert!(screen.contains("D  E  F"));
+        assert!(!screen.contains("ab"));
+
+        output.clear();
+        view.hex_view.set_uppercase(false);
+        view.draw(&model).unwrap();
+        let screen = output.screen();
+        assert!(screen.contains("ab ab"));
+        assert!(screen.contains("c0: "));
+    }
+
+    #[test]
+    fn test_draw_cursorline() {
+        use crate::UsizeMax;
+
+        let (mut view, output) = headless();
+
+        let mut model = Model::new();
+        model.buffer = vec![0x41; 40].into();
+        model.caret = Caret::Offset(UsizeMax::new(20, 39));
+
+        let underlay = format!("{}{}10:", Faint, Invert);
+        view.draw(&model).unwrap();
+        assert!(!output.screen().contains(&underlay));
+
+        output.clear();
+        view.hex_view.set_cursorline(true);
+        view.draw(&model).unwrap();
+        let screen = output.screen();
+
+        // The row is drawn before the caret, which comes on top
+        let row = screen.find(&underlay).unwrap();
+        assert!(screen[row..].contains(&"A".repeat(16)));
+        assert!(screen.rfind(&format!("{}41", Invert)).unwrap() > row);
+    }
+
+    #[test]
+    fn test_draw_tildes() {
+        use crate::UsizeMax;
+
+        let (mut view, output) = headless();
+
+        let tildes = |view: &View, model: &Model| {
+            output.clear();
+            view.draw(model).unwrap();
+            let screen = output.screen();
+            (1..=24)
+                .filter(|&line| screen.contains(&format!("{}~", Goto(1, line))))
+                .collect::<Vec<u16>>()
+        };
+
+        // Three rows below the header, the status takes the last two lines
+        let mut model = Model::new();
+        model.buffer = vec![0; 40].into();
+        assert_eq!(tildes(&view, &model), (5..=22).collect::<Vec<_>>());
+
+        // The caret after a full last row needs a row of its own
+        model.buffer = vec![0; 32].into();
+        model.caret = Caret::Index(UsizeMax::new(32, 32));
+        assert_eq!(tildes(&view, &model), (5..=22).collect::<Vec<_>>());
+
+        view.set_laststatus(false);
+        assert_eq!(tildes(&view, &model), (5..=23).collect::<Vec<_>>());
+
+        // A full screen leaves no room
+        model.buffer = vec![0; 0x1000].into();
+        model.caret = Caret::Offset(UsizeMax::new(0, 0xfff));
+        assert_eq!(tildes(&view, &model), vec![]);
+    }
+
+    #[test]
+    fn test_draw_colorcolumn() {
+        let (mut view, output) = headless();
+        view.hex_view.set_colorcolumn(Some(3));
+
+        let mut model = Model::new();
+        model.buffer = (0..0x13).collect();
+        view.draw(&model).unwrap();
+        let screen = output.screen();
+
+        let shaded = |hex: &str, ascii: &str, line: u16| {
+            format!(
+                "{}{}{}{}{}{}",
+                Bg(AnsiValue::grayscale(5)),
+                Goto(14, line),
+                hex,
+                Goto(57, line),
+                ascii,
+                Bg(ColorReset)
+            )
+        };
+        assert!(screen.contains(&shaded("03", ".", 2)));
+        // The last row is too short for it
+        assert!(!screen.contains(&shaded("13", ".", 3)));
+        assert_eq!(
+            screen
+                .matches(&format!("{}", Bg(AnsiValue::grayscale(5))))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_encoding_decode() {
+        let bytes = [0x01, 0x41, 0x7f, 0xad, 0xb0, 0xc3, 0xa9, 0xdb, 0xff];
+
+        assert_eq!(
+            Encoding::Ascii
+                .decode(&bytes)
+                .into_iter()
+                .collect::<String>(),
+            ".A......."
+        );
+        assert_eq!(
+            Encoding::Latin1
+                .decode(&bytes)
+                .into_iter()
+                .collect::<String>(),
+            ".A..°Ã©Ûÿ"
+        );
+        assert_eq!(
+            Encoding::Cp437
+                .decode(&bytes)
+                .into_iter()
+                .collect::<String>(),
+            "☺A⌂¡░├⌐█ "
+        );
+        // `é` is drawn at its lead byte, the others are no UTF-8 of their own
+        assert_eq!(
+            Encoding::Utf8
+                .decode(&bytes)
+                .into_iter()
+                .collect::<String>(),
+            ".A...é·.."
+        );
+        // A character cut off by the end of the row doesn't decode
+        assert_eq!(
+            Encoding::Utf8.decode(&[0x41, 0xe2, 0x82]),
+            vec!['A', '.', '.']
+        );
+    }
+
+    #[test]
+    fn test_draw_encoding() {
+        let (mut view, output) = headless();
+        view.hex_view.set_encoding(Encoding::Utf8);
+
+        let mut model = Model::new();
+        model.buffer = "a€b".bytes().collect();
+        view.draw(&model).unwrap();
+        let screen = output.screen();
+
+        assert!(screen.contains(&format!("{}a€··b", Goto(53, 2))));
+    }
+
+    #[test]
+    fn test_draw_wrap() {
+        use crate::UsizeMax;
+
+        let (mut view, output) = headless();
+        view.hex_view.set_encoding(Encoding::Utf8);
+
+        let mut model = Model::new();
+        model.buffer = "a漢b".bytes().collect();
+        view.draw(&model).unwrap();
+        let screen = output.screen();
+        assert!(screen.contains(&format!("{}a.··b", Goto(53, 2))));
+
+        // The glyph takes the cell of its second byte, which underlines the whole glyph
+        output.clear();
+        view.hex_view.set_wrap(true);
+        model.caret = Caret::Offset(UsizeMax::new(2, 4));
+        view.draw(&model).unwrap();
+        let screen = output.screen();
+        assert!(screen.contains(&format!("{}a漢·b", Goto(53, 2))));
+        assert!(screen.contains(&format!("{}{}漢{}", Goto(54, 2), Underline, StyleReset)));
+    }
+
+    #[test]
+    fn test_draw_matchbyte() {
+        use crate::UsizeMax;
+
+        let (mut view, output) = headless();
+        view.hex_view.set_matchbyte(true);
+
+        let mut model = Model::new();
+        model.buffer = vec![0, 0x41, 0, 0x41, 0x41].into();
+        model.caret = Caret::Offset(UsizeMax::new(1, 4));
+        view.draw(&model).unwrap();
+        let screen = output.screen();
+
+        let highlighted = |column: u16| {
+            format!(
+                "{}{}41{}A{}",
+                Bg(AnsiValue::grayscale(3)),
+                Goto(4 + column * 3, 2),
+                Goto(53 + column, 2),
+                Bg(ColorReset)
+            )
+        };
+        // Not the caret's own byte
+        assert!(!screen.contains(&highlighted(1)));
+        assert!(screen.contains(&highlighted(3)));
+        assert!(screen.contains(&highlighted(4)));
+        assert_eq!(
+            screen
+                .matches(&format!("{}", Bg(AnsiValue::grayscale(3))))
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_draw_notes() {
+        let (mut view, output) = headless();
+
+        let mut model = Model::new();
+        model.buffer = (0..0x20).collect();
+        model.notes.insert(0x11, "length".into());
+        view.status_view.note = Some("magic".into());
+        view.draw(&model).unwrap();
+        let screen = output.screen();
+
+        assert!(screen.contains(&format!(
+            "{}{}{}11{}",
+            Goto(8, 3),
+            Fg(Yellow),
+            Underline,
+            NoUnderline
+        )));
+        assert!(screen.contains("note: magic"));
+
+        // Messages take precedence
+        output.clear();
+        view.status_view.set_body("saved");
+        view.draw(&model).unwrap();
+        assert!(!output.screen().contains("note: magic"));
+    }
+
+    #[test]
+    fn test_draw_showcmd() {
+        let mut status = StatusView::new();
+        status.set_area(DrawArea {
+            origin: (1, 23),
+            dimens: (40, 2),
+        });
+        status.set_body(&"x".repeat(40));
+        status.pending = Some("123456789012d".into());
+
+        let mut output = Vec::new();
+        status.draw(&mut output).unwrap();
+        let output = String::from_utf8_lossy(&output).into_owned();
+
+        // "0x0 (0)" takes the last 7 columns, the keys the 10 before, with a space on either side
+        assert!(output.contains(&format!("{}456789012d", Goto(23, 24))));
+        assert!(output.contains(&format!("{}{}", ClearCurrentLine, "x".repeat(20) + "…")));
+        assert!(!output.contains(&"x".repeat(21)));
+    }
+
+    #[test]
+    fn test_draw_nonumber() {
+        let (mut view, output) = headless();
+        view.hex_view.set_number(false);
+        view.hex_view.set_cursorline(true);
+
+        let mut model = Model::new();
+        model.buffer = vec![0xab; 0x20].into();
+        view.draw(&model).unwrap();
+        let screen = output.screen();
+
+        // The hex starts in the first column, below the index header
+        assert!(screen.contains(&format!("{}0  1  2", Goto(2, 1))));
+        assert!(screen.contains(&format!("{}ab ab", Goto(1, 3))));
+        assert!(!screen.contains("00000010"));
+
+        // Clicks are mapped to the moved panes as well
+        assert_eq!(view.hex_view.cell_to_offset(1, 3, model.len()), Some(0x10));
+        assert_eq!(view.hex_view.cell_to_offset(50, 2, model.len()), Some(0));
+    }
+
+    #[test]
+    fn test_draw_relativenumber() {
+        use crate::UsizeMax;
+
+        let (mut view, output) = headless();
+        view.hex_view.set_relativenumber(true);
+
+        let mut model = Model::new();
+        model.buffer = vec![0; 0x100].into();
+        model.caret = Caret::Offset(UsizeMax::new(0x35, 0xff));
+        view.draw(&model).unwrap();
+        let screen = output.screen();
+
+        // The caret is on the fourth row, below the index header
+        for (line, number) in ["  3", "  2", "  1", "030", "  1", "  2"]
+            .iter()
+            .enumerate()
+        {
+            assert!(screen.contains(&format!(
+                "{}{}{}:",
+                Goto(1, 2 + line as u16),
+                Fg(Red),
+                number
+            )));
+        }
+    }
+
+    #[test]
+    fn test_cell_to_offset() {
+        let (view, _) = headless();
+        let hex_view = &view.hex_view;
+
+        // 0xff bytes need 2 offset digits, then the hex pane starts at 5 and ASCII at 54
+        assert_eq!(hex_view.cell_to_offset(5, 2, 0xff), Some(0));
+        assert_eq!(hex_view.cell_to_offset(6, 2, 0xff), Some(0));
+        assert_eq!(hex_view.cell_to_offset(7, 2, 0xff), None);
+        assert_eq!(hex_view.cell_to_offset(50, 3, 0xff), Some(31));
+        assert_eq!(hex_view.cell_to_offset(54, 2, 0xff), Some(0));
+        assert_eq!(hex_view.cell_to_offset(69, 4, 0xff), Some(47));
+
+        // Offsets, the header, the status and the gap between the panes
+        assert_eq!(hex_view.cell_to_offset(1, 2, 0xff), None);
+        assert_eq!(hex_view.cell_to_offset(5, 1, 0xff), None);
+        assert_eq!(hex_view.cell_to_offset(5, 23, 0xff), None);
+        assert_eq!(hex_view.cell_to_offset(52, 2, 0xff), None);
+
+        // Beyond the end of a partial row
+        assert_eq!(hex_view.cell_to_offset(8, 2, 1), None);
+        assert_eq!(hex_view.cell_to_offset(53, 2, 1), Some(0));
+
+        // Rows count from the scroll position
+        let mut view = view;
+        view.hex_view.scroll_start = 32;
+        assert_eq!(view.hex_view.cell_to_offset(5, 3, 0xff), Some(48));
+    }
+
     #[test]
     fn test_range_to_marker() {
         assert_eq!(range_to_marker(0, 16), vec![(0, 0, 15), (1, 0, 0)]);
         assert_eq!(range_to_marker(8, 18), vec![(0, 8, 15), (1, 0, 2)]);
+        assert_eq!(range_to_marker(18, 8), vec![(0, 8, 15), (1, 0, 2)]);
+    }
+
+    #[test]
+    fn test_range_to_marker_single_cell() {
+        assert_eq!(range_to_marker(0, 0), vec![(0, 0, 0)]);
+        assert_eq!(range_to_marker(5, 5), vec![(0, 5, 5)]);
+        assert_eq!(range_to_marker(15, 15), vec![(0, 15, 15)]);
+        assert_eq!(range_to_marker(16, 16), vec![(1, 0, 0)]);
+        assert_eq!(range_to_marker(15, 16), vec![(0, 15, 15), (1, 0, 0)]);
+        assert_eq!(range_to_marker(65535, 65535), vec![(4095, 15, 15)]);
     }
 }
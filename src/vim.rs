@@ -1,6 +1,11 @@
+use std::convert::TryFrom;
+
 use termion::event::Key::{self, Backspace, Char};
 
-use crate::controller::{Direction, Msg};
+use crate::{
+    controller::{self, ClipboardKind, Direction, LineEnding, Msg, Operator, Setting},
+    view::{self, CaretStyle, BYTES_PER_ROW},
+};
 
 #[derive(Copy, Clone, Debug)]
 pub enum InputMode {
@@ -33,7 +38,7 @@ impl InputStateMachine {
         match self.mode {
             //InputMode::Binary => is_binary(c),
             InputMode::Hex => c.is_ascii_hexdigit(),
-            InputMode::Ascii => c.is_ascii_graphic(),
+            InputMode::Ascii => c == ' ' || c.is_ascii_graphic(),
         }
     }
 
@@ -89,50 +94,471 @@ impl InputStateMachine {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum VimState {
     Normal,
     Insert(InputStateMachine),
     Replace(InputStateMachine, bool),
+    // Overwrites the current byte one nibble at a time, true once the high nibble is set
+    Nibble(bool),
+    // Reads the hex code of a byte to insert literally, e.g. control characters in ASCII mode
+    Literal(InputStateMachine),
     Visual,
     Command(String),
+    Search(String),
+    // Reads the byte to jump to with `f`
+    Find(InputStateMachine),
+    // Waits for the motion an operator like `d` applies to
+    Operator(Operator),
+    // Waits for the key after a `g` prefix, like `gI`
+    Goto,
+    // Waits for the text object to select after `i` in visual mode, like `iw`
+    TextObject,
+    // Asks whether to save before an action that would lose changes
+    Confirm(PendingAction),
+    // Scrolls the help until it is closed
+    Help,
+}
+
+// Actions held back by a confirmation prompt
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PendingAction {
+    Quit,
 }
 
 impl Msg {
     pub fn parse(cmd: &str) -> Result<Msg, &'static str> {
         use self::Msg::*;
 
-        if let Some(stripped) = cmd.strip_prefix("w ") {
-            return Ok(SaveAs(stripped.trim().into()));
+        // Checked first, `w >> file` would be saved as ">> file" otherwise
+        if let Some(stripped) = cmd
+            .strip_prefix('w')
+            .and_then(|rest| rest.trim_start().strip_prefix(">>"))
+        {
+            return match stripped.trim() {
+                "" => Err("no file to append to"),
+                path => Ok(Append(path.into())),
+            };
+        }
+
+        for &(prefix, force) in &[
+            ("w ", false),
+            ("w! ", true),
+            ("saveas ", false),
+            ("saveas! ", true),
+        ] {
+            if let Some(stripped) = cmd.strip_prefix(prefix) {
+                return Ok(SaveAs(stripped.trim().into(), force));
+            }
+        }
+
+        for &(prefix, force) in &[("export ", false), ("export! ", true)] {
+            if let Some(stripped) = cmd.strip_prefix(prefix) {
+                let mut parts = stripped.trim().splitn(2, char::is_whitespace);
+                if parts.next() != Some("xxd") {
+                    return Err("unknown export format, expected \"xxd\"");
+                }
+
+                // An optional bytes per line may precede the file name
+                let rest = parts.next().unwrap_or("").trim();
+                let mut parts = rest.splitn(2, char::is_whitespace);
+                let (width, path) = match parts.next().map(str::parse::<usize>) {
+                    Some(Ok(width)) => (Some(width), parts.next().unwrap_or("").trim()),
+                    _ => (None, rest),
+                };
+
+                return match (width, path) {
+                    (_, "") => Err("no file to export to"),
+                    (Some(width), _) if width == 0 || width > 256 => {
+                        Err("expected a width from 1 to 256")
+                    }
+                    (width, path) => Ok(Export(path.into(), width, force)),
+                };
+            }
+        }
+
+        if let Some(stripped) = cmd.strip_prefix('!') {
+            return match stripped.trim() {
+                "" => Err("no command to filter through"),
+                filter => Ok(Filter(filter.into())),
+            };
+        }
+
+        if let Some(stripped) = cmd.strip_prefix("map ") {
+            let mut parts = stripped.trim().splitn(2, char::is_whitespace);
+            let (lhs, rhs) = (
+                parts.next().unwrap_or(""),
+                parts.next().unwrap_or("").trim(),
+            );
+            let rhs = rhs.strip_prefix(':').unwrap_or(rhs);
+
+            let mut chars = lhs.chars();
+            return match (chars.next(), chars.next()) {
+                (Some(key), None) if !rhs.is_empty() => {
+                    // Reject mappings to invalid commands right away
+                    Msg::parse(rhs)?;
+                    Ok(Map(Key::Char(key), rhs.into()))
+                }
+                _ => Err("usage: map <key> <command>"),
+            };
+        }
+
+        for prefix in &["e ", "edit "] {
+            if let Some(stripped) = cmd.strip_prefix(prefix) {
+                return match stripped.trim() {
+                    "" => Err("no file to open"),
+                    path => Ok(Open(path.into())),
+                };
+            }
+        }
+
+        if cmd == "ascii" {
+            return Ok(Show(ASCII_REFERENCE.into()));
+        }
+
+        if let Some(stripped) = cmd.strip_prefix("ascii ") {
+            return describe_ascii(stripped.trim()).map(Show);
+        }
+
+        if let Some(stripped) = cmd.strip_prefix("select ") {
+            let mut parts = stripped.split_whitespace().map(parse_offset);
+            return match (parts.next(), parts.next(), parts.next()) {
+                (Some(Some(anchor)), Some(Some(end)), None) => Ok(SelectRange(anchor, end)),
+                _ => Err("usage: select <start> <end>"),
+            };
+        }
+
+        // Without text, like plain `note`, it clears the note
+        if let Some(stripped) = cmd.strip_prefix("note ") {
+            return match stripped.trim() {
+                "" => Ok(Note(None)),
+                note => Ok(Note(Some(note.into()))),
+            };
+        }
+
+        if let Some(stripped) = cmd.strip_prefix("list ") {
+            return match parse_hex_pattern(stripped)? {
+                pattern if pattern.is_empty() => Err("usage: list <hex pattern>"),
+                pattern => Ok(List(pattern)),
+            };
+        }
+
+        // An empty regex would match at every offset
+        if let Some(stripped) = cmd.strip_prefix("grep ") {
+            return match stripped {
+                "" => Err("usage: grep <regex>"),
+                regex => Ok(Grep(regex.into())),
+            };
+        }
+
+        if let Some(stripped) = cmd.strip_prefix("timestamp ") {
+            return match stripped.trim() {
+                "4" => Ok(Timestamp(4)),
+                "8" => Ok(Timestamp(8)),
+                _ => Err("expected a width of 4 or 8 bytes"),
+            };
+        }
+
+        if let Some(stripped) = cmd.strip_prefix("swap ") {
+            return match parse_offset(stripped.trim()) {
+                Some(width) if width > 0 => Ok(Swap(width)),
+                _ => Err("expected a group size of at least 1"),
+            };
+        }
+
+        if let Some(stripped) = cmd.strip_prefix("set ") {
+            let mut settings = Setting::parse_all(stripped);
+            return match settings.len() {
+                0 => Err("no option to set"),
+                1 => settings.remove(0).map(Set),
+                _ => Ok(SetMany(settings)),
+            };
         }
 
         match cmd {
-            "q" => Ok(Quit),
-            "q!" => Ok(QuitWithoutSaving),
-            "w" => Ok(Save),
-            "wq" | "x" => Ok(SaveAndQuit),
+            // There is only a single buffer, so the "all" variants act on it alone
+            "q" | "qa" => Ok(Quit),
+            "q!" | "qa!" => Ok(QuitWithoutSaving),
+            "w" | "wa" => Ok(Save),
+            "wq" | "x" | "wqa" | "xa" => Ok(SaveAndQuit),
+            "sort" => Ok(Sort(false)),
+            "sort!" => Ok(Sort(true)),
+            "reverse" => Ok(Reverse),
+            "info" => Ok(Info),
+            "help" | "h" => Ok(Help(true)),
+            "note" => Ok(Note(None)),
+            "timestamp" => Ok(Timestamp(4)),
+            "redraw" => Ok(Repaint),
+            "edit-external" => Ok(EditExternal),
+            "copy" => Ok(ClipboardCopy(false)),
+            "copy!" => Ok(ClipboardCopy(true)),
             offset => {
-                // If none of the above commands, try to interpret as jump command...
-
-                let (skip, base) = if offset.starts_with("0b") {
-                    (2, 2)
-                } else if offset.starts_with("08") {
-                    (2, 8)
-                } else if offset.starts_with("0x") {
-                    (2, 16)
+                // If none of the above commands, try to interpret as (relative) jump command...
+                let direction = if let Some(stripped) = offset.strip_suffix('%') {
+                    stripped
+                        .parse()
+                        .ok()
+                        .map(|percent| Ok(Direction::Percent(percent)))
+                } else if let Some(stripped) = offset.strip_prefix('+') {
+                    parse_offset(stripped)
+                        .map(|offset| isize::try_from(offset).map(Direction::Relative))
+                } else if let Some(stripped) = offset.strip_prefix('-') {
+                    parse_offset(stripped).map(|offset| {
+                        isize::try_from(offset).map(|offset| Direction::Relative(-offset))
+                    })
                 } else {
-                    (0, 10)
+                    parse_offset(offset).map(|offset| Ok(Direction::Offset(offset)))
                 };
 
                 // ...and error out if no valid offset. (Proper parsing may be implemented in the future.)
-                if let Ok(offset) = usize::from_str_radix(&offset[skip..], base) {
-                    Ok(Move(Direction::Offset(offset)))
-                } else {
-                    Err("no such command")
+                match direction {
+                    Some(Ok(direction)) => Ok(Move(direction)),
+                    // Casting would wrap around, e.g. +0xffffffffffffffff to -1
+                    Some(Err(_)) => Err("relative offset out of range"),
+                    None => Err("no such command"),
+                }
+            }
+        }
+    }
+}
+
+const ASCII_REFERENCE: &str =
+    "00 NUL  09 TAB  0a LF  0d CR  1b ESC  20 SP  30-39 0-9  41-5a A-Z  61-7a a-z  7f DEL";
+
+const CONTROL_NAMES: [&str; 32] = [
+    "NUL", "SOH", "STX", "ETX", "EOT", "ENQ", "ACK", "BEL", "BS", "TAB", "LF", "VT", "FF", "CR",
+    "SO", "SI", "DLE", "DC1", "DC2", "DC3", "DC4", "NAK", "SYN", "ETB", "CAN", "EM", "SUB", "ESC",
+    "FS", "GS", "RS", "US",
+];
+
+fn ascii_name(byte: u8) -> String {
+    match byte {
+        0x00..=0x1f => CONTROL_NAMES[byte as usize].into(),
+        b' ' => "SP".into(),
+        0x7f => "DEL".into(),
+        _ => format!("'{}'", byte as char),
+    }
+}
+
+// A single character shows its code, anything else is read as a byte value to show the character for
+fn describe_ascii(arg: &str) -> Result<String, &'static str> {
+    let mut chars = arg.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii() => Ok(format!(
+            "{} = 0x{:02x} = {}",
+            ascii_name(c as u8),
+            c as u8,
+            c as u8
+        )),
+        (Some(_), None) => Err("not an ASCII character"),
+        _ => match parse_offset(arg) {
+            Some(value) if value <= 0x7f => Ok(format!(
+                "0x{:02x} = {} = {}",
+                value,
+                value,
+                ascii_name(value as u8)
+            )),
+            Some(_) => Err("not an ASCII code, expected 0 to 0x7f"),
+            None => Err("usage: ascii [<char>|<code>]"),
+        },
+    }
+}
+
+fn parse_offset(offset: &str) -> Option<usize> {
+    let (skip, base) = if offset.starts_with("0b") {
+        (2, 2)
+    } else if offset.starts_with("08") {
+        (2, 8)
+    } else if offset.starts_with("0x") {
+        (2, 16)
+    } else {
+        (0, 10)
+    };
+
+    usize::from_str_radix(&offset[skip..], base).ok()
+}
+
+impl Setting {
+    pub fn parse(option: &str) -> Result<Setting, &'static str> {
+        use self::Setting::*;
+
+        let mut words = option.split_whitespace();
+
+        match (words.next(), words.next(), words.next()) {
+            (Some("snap"), Some(value), None) => Ok(Snap(parse_switch(value)?)),
+            (Some("wrapscan"), Some(value), None) => Ok(Wrapscan(parse_switch(value)?)),
+            (Some("incsearch"), Some(value), None) => Ok(IncSearch(parse_switch(value)?)),
+            (Some("ignorecase"), Some(value), None) => Ok(IgnoreCase(parse_switch(value)?)),
+            (Some("showcmd"), Some(value), None) => Ok(ShowCmd(parse_switch(value)?)),
+            (Some("nibble"), Some(value), None) => Ok(Nibble(parse_switch(value)?)),
+            (Some("errorbell"), Some(value), None) => Ok(ErrorBell(parse_switch(value)?)),
+            (Some("tildes"), Some(value), None) => Ok(Tildes(parse_switch(value)?)),
+            (Some("backupcopy"), Some(value), None) => Ok(BackupCopy(parse_switch(value)?)),
+            (Some("swapfile"), Some(value), None) => Ok(SwapFile(parse_switch(value)?)),
+            (Some("cursorline"), Some(value), None) => Ok(CursorLine(parse_switch(value)?)),
+            (Some("relativenumber"), Some(value), None) => Ok(RelativeNumber(parse_switch(value)?)),
+            (Some("number"), Some(value), None) => Ok(Number(parse_switch(value)?)),
+            (Some("number"), None, None) => Ok(Number(true)),
+            (Some("nonumber"), None, None) => Ok(Number(false)),
+            (Some("mouse"), Some(value), None) => Ok(Mouse(parse_switch(value)?)),
+            (Some("wrap"), Some(value), None) => Ok(Wrap(parse_switch(value)?)),
+            (Some("matchbyte"), Some(value), None) => Ok(MatchByte(parse_switch(value)?)),
+            (Some("matchbyte"), None, None) => Ok(MatchByte(true)),
+            (Some("nomatchbyte"), None, None) => Ok(MatchByte(false)),
+            (Some("virtualedit"), Some(value), None) => Ok(VirtualEdit(parse_switch(value)?)),
+            (Some("signed"), Some(value), None) => Ok(Signed(parse_switch(value)?)),
+            // There is only a single buffer, so both only apply to opening another file
+            (Some("autowrite" | "autowriteall"), Some(value), None) => {
+                Ok(AutoWrite(parse_switch(value)?))
+            }
+            (Some("entropy"), Some(value), None) => Ok(Entropy(parse_switch(value)?)),
+            (Some("laststatus"), Some(value), None) => Ok(LastStatus(parse_switch(value)?)),
+            (Some("numberwidth"), Some(value), None) => match value.parse() {
+                Ok(width) if width <= 16 => Ok(NumberWidth(width)),
+                _ => Err("expected a width from 0 to 16"),
+            },
+            (Some("colorcolumn"), None, None) => Ok(ColorColumn(None)),
+            (Some("colorcolumn"), Some(value), None) => match value.parse() {
+                Ok(column) if column < BYTES_PER_ROW => Ok(ColorColumn(Some(column))),
+                _ => Err("expected a column from 0 to 15"),
+            },
+            (Some("encoding"), Some(value), None) => match value {
+                "ascii" => Ok(Encoding(view::Encoding::Ascii)),
+                "latin1" => Ok(Encoding(view::Encoding::Latin1)),
+                "cp437" => Ok(Encoding(view::Encoding::Cp437)),
+                "utf8" | "utf-8" => Ok(Encoding(view::Encoding::Utf8)),
+                _ => Err("expected \"ascii\", \"latin1\", \"cp437\" or \"utf8\""),
+            },
+            (Some("endian"), Some(value), None) => match value {
+                "little" => Ok(Endian(controller::Endian::Little)),
+                "big" => Ok(Endian(controller::Endian::Big)),
+                _ => Err("expected \"little\" or \"big\""),
+            },
+            (Some("clipboard"), Some(value), None) => match value {
+                "system" => Ok(Clipboard(ClipboardKind::System)),
+                "osc52" => Ok(Clipboard(ClipboardKind::Osc52)),
+                _ => Err("expected \"system\" or \"osc52\""),
+            },
+            (Some("fileformat"), Some(value), None) => match value {
+                "unix" => Ok(FileFormat(LineEnding::Unix)),
+                "dos" => Ok(FileFormat(LineEnding::Dos)),
+                "mac" => Ok(FileFormat(LineEnding::Mac)),
+                _ => Err("expected \"unix\", \"dos\" or \"mac\""),
+            },
+            (Some("record"), Some(value), None) => match value.parse() {
+                Ok(record) => Ok(Record(record)),
+                _ => Err("expected a record size, 0 for none"),
+            },
+            (Some("undo"), Some(value), None) => Ok(Undo(parse_switch(value)?)),
+            (Some("noundo"), None, None) => Ok(Undo(false)),
+            (Some("undolevels"), Some(value), None) => match value.parse() {
+                Ok(levels) => Ok(UndoLevels(levels)),
+                _ => Err("expected a number of undo steps"),
+            },
+            (Some("scrolloff"), Some(value), None) => match value.parse() {
+                Ok(rows) => Ok(ScrollOff(rows)),
+                _ => Err("expected a number of rows"),
+            },
+            (Some("hexcase"), Some(value), None) => match value {
+                "upper" => Ok(HexCase(true)),
+                "lower" => Ok(HexCase(false)),
+                _ => Err("expected \"upper\" or \"lower\""),
+            },
+            (Some("caret"), None, None) => Ok(Caret(None)),
+            (Some("caret"), Some(value), None) => match value {
+                "block" => Ok(Caret(Some(CaretStyle::Block))),
+                "bar" => Ok(Caret(Some(CaretStyle::Bar))),
+                "underline" => Ok(Caret(Some(CaretStyle::Underline))),
+                _ => Err("expected \"block\", \"bar\" or \"underline\""),
+            },
+            _ => Err("no such option"),
+        }
+    }
+
+    // Parses several options in a row, e.g. "snap on caret nibble on". Each option takes the
+    // word after it as value if it can, so the invalid ones can be skipped.
+    pub fn parse_all(options: &str) -> Vec<Result<Setting, &'static str>> {
+        let words: Vec<_> = options.split_whitespace().collect();
+        let mut settings = vec![];
+        let mut i = 0;
+
+        while i < words.len() {
+            let with_value = words
+                .get(i + 1)
+                .map(|value| Setting::parse(&format!("{} {}", words[i], value)));
+
+            match (with_value, Setting::parse(words[i])) {
+                (Some(Ok(setting)), _) => {
+                    settings.push(Ok(setting));
+                    i += 2;
+                }
+                (_, Ok(setting)) => {
+                    settings.push(Ok(setting));
+                    i += 1;
                 }
+                // A known option with an invalid value
+                (Some(Err(error)), _) if error != "no such option" => {
+                    settings.push(Err(error));
+                    i += 2;
+                }
+                (_, Err(error)) => {
+                    settings.push(Err(error));
+                    i += 1;
+                }
+            }
+        }
+
+        settings
+    }
+}
+
+// Parses a search pattern of hex digits, whitespace is ignored (e.g. "de ad be ef")
+// `??` or `.` match any byte (e.g. "de ?? ef")
+pub fn parse_hex_pattern(pattern: &str) -> Result<Vec<Option<u8>>, &'static str> {
+    let pattern: Vec<char> = pattern.chars().filter(|c| !c.is_whitespace()).collect();
+    let mut bytes = Vec::with_capacity(pattern.len() / 2);
+    let mut i = 0;
+
+    while i < pattern.len() {
+        match (pattern[i], pattern.get(i + 1)) {
+            ('.', _) => {
+                bytes.push(None);
+                i += 1;
+            }
+            ('?', Some('?')) => {
+                bytes.push(None);
+                i += 2;
             }
+            (high, Some(&low)) => match (high.to_digit(16), low.to_digit(16)) {
+                (Some(high), Some(low)) => {
+                    bytes.push(Some((high << 4 | low) as u8));
+                    i += 2;
+                }
+                _ => return Err(PATTERN_ERROR),
+            },
+            _ => return Err(PATTERN_ERROR),
         }
     }
+
+    Ok(bytes)
+}
+
+const PATTERN_ERROR: &str = "invalid pattern: expected pairs of hex digits, ?? or .";
+
+// Like `hex::encode`, with `??` for any byte
+pub fn format_hex_pattern(pattern: &[Option<u8>]) -> String {
+    pattern
+        .iter()
+        .map(|byte| byte.map_or("??".into(), |byte| format!("{:02x}", byte)))
+        .collect()
+}
+
+fn parse_switch(value: &str) -> Result<bool, &'static str> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err("expected \"on\" or \"off\""),
+    }
 }
 
 #[cfg(test)]
@@ -146,4 +572,334 @@ mod tests {
         let _ = Msg::parse(&input);
         true
     }
+
+    #[test]
+    fn test_msg_parse_relative() {
+        assert!(matches!(
+            Msg::parse("+16"),
+            Ok(Msg::Move(Direction::Relative(16)))
+        ));
+        assert!(matches!(
+            Msg::parse("-0x10"),
+            Ok(Msg::Move(Direction::Relative(-16)))
+        ));
+        assert!(matches!(
+            Msg::parse("0x10"),
+            Ok(Msg::Move(Direction::Offset(16)))
+        ));
+        assert!(Msg::parse("+").is_err());
+        assert!(Msg::parse("+-1").is_err());
+        assert_eq!(
+            Msg::parse("+0xffffffffffffffff").err(),
+            Some("relative offset out of range")
+        );
+        assert_eq!(
+            Msg::parse("-0x8000000000000000").err(),
+            Some("relative offset out of range")
+        );
+    }
+
+    #[test]
+    fn test_msg_parse_export() {
+        assert!(matches!(
+            Msg::parse("export xxd out.txt"),
+            Ok(Msg::Export(ref path, None, false)) if path == "out.txt"
+        ));
+        assert!(matches!(
+            Msg::parse("export! xxd 32 out file.txt"),
+            Ok(Msg::Export(ref path, Some(32), true)) if path == "out file.txt"
+        ));
+        assert!(Msg::parse("export xxd").is_err());
+        assert!(Msg::parse("export xxd 32").is_err());
+        assert!(Msg::parse("export xxd 0 out.txt").is_err());
+        assert!(Msg::parse("export hexdump out.txt").is_err());
+    }
+
+    #[test]
+    fn test_msg_parse_append() {
+        assert!(matches!(
+            Msg::parse("w >> log.bin"),
+            Ok(Msg::Append(ref path)) if path == "log.bin"
+        ));
+        assert!(matches!(
+            Msg::parse("w>>log.bin"),
+            Ok(Msg::Append(ref path)) if path == "log.bin"
+        ));
+        assert!(Msg::parse("w >>").is_err());
+        assert!(matches!(
+            Msg::parse("w > log.bin"),
+            Ok(Msg::SaveAs(ref path, false)) if path == "> log.bin"
+        ));
+    }
+
+    #[test]
+    fn test_msg_parse_select() {
+        assert!(matches!(
+            Msg::parse("select 0x10 0x2f"),
+            Ok(Msg::SelectRange(16, 47))
+        ));
+        assert!(matches!(
+            Msg::parse("select  47   16 "),
+            Ok(Msg::SelectRange(47, 16))
+        ));
+        assert!(Msg::parse("select 16").is_err());
+        assert!(Msg::parse("select 1 2 3").is_err());
+        assert!(Msg::parse("select a b").is_err());
+    }
+
+    #[test]
+    fn test_msg_parse_list() {
+        assert!(
+            matches!(Msg::parse("list ab ?? cd"), Ok(Msg::List(ref pattern)) if pattern == &[Some(0xab), None, Some(0xcd)])
+        );
+        assert!(Msg::parse("list  ").is_err());
+        assert!(Msg::parse("list xy").is_err());
+    }
+
+    #[test]
+    fn test_msg_parse_timestamp() {
+        assert!(matches!(Msg::parse("timestamp"), Ok(Msg::Timestamp(4))));
+        assert!(matches!(Msg::parse("timestamp 8"), Ok(Msg::Timestamp(8))));
+        assert!(Msg::parse("timestamp 2").is_err());
+    }
+
+    #[test]
+    fn test_msg_parse_edit() {
+        assert!(matches!(
+            Msg::parse("e other.bin"),
+            Ok(Msg::Open(ref path)) if path == "other.bin"
+        ));
+        assert!(matches!(
+            Msg::parse("edit  other file.bin "),
+            Ok(Msg::Open(ref path)) if path == "other file.bin"
+        ));
+        assert!(Msg::parse("e  ").is_err());
+        assert!(matches!(
+            Setting::parse("autowriteall on"),
+            Ok(Setting::AutoWrite(true))
+        ));
+    }
+
+    #[test]
+    fn test_msg_parse_ascii() {
+        assert!(matches!(Msg::parse("ascii"), Ok(Msg::Show(ref msg)) if msg == ASCII_REFERENCE));
+        assert!(matches!(
+            Msg::parse("ascii A"),
+            Ok(Msg::Show(ref msg)) if msg == "'A' = 0x41 = 65"
+        ));
+        assert!(matches!(
+            Msg::parse("ascii 0x41"),
+            Ok(Msg::Show(ref msg)) if msg == "0x41 = 65 = 'A'"
+        ));
+        assert!(matches!(
+            Msg::parse("ascii 27"),
+            Ok(Msg::Show(ref msg)) if msg == "0x1b = 27 = ESC"
+        ));
+        assert!(Msg::parse("ascii  ").is_err());
+        assert!(Msg::parse("ascii 0x80").is_err());
+        assert!(Msg::parse("ascii ä").is_err());
+        assert!(Msg::parse("ascii AB").is_err());
+    }
+
+    #[test]
+    fn test_msg_parse_grep() {
+        assert!(matches!(Msg::parse("grep a+ b"), Ok(Msg::Grep(ref re)) if re == "a+ b"));
+        assert!(Msg::parse("grep").is_err());
+        assert_eq!(Msg::parse("grep ").err(), Some("usage: grep <regex>"));
+    }
+
+    #[test]
+    fn test_msg_parse_note() {
+        assert!(
+            matches!(Msg::parse("note  magic "), Ok(Msg::Note(Some(ref note))) if note == "magic")
+        );
+        assert!(matches!(Msg::parse("note"), Ok(Msg::Note(None))));
+        assert!(matches!(Msg::parse("note  "), Ok(Msg::Note(None))));
+    }
+
+    #[test]
+    fn test_msg_parse_help() {
+        assert!(matches!(Msg::parse("help"), Ok(Msg::Help(true))));
+        assert!(matches!(Msg::parse("h"), Ok(Msg::Help(true))));
+        assert!(Msg::parse("help me").is_err());
+    }
+
+    #[test]
+    fn test_msg_parse_percent() {
+        assert!(matches!(
+            Msg::parse("50%"),
+            Ok(Msg::Move(Direction::Percent(50)))
+        ));
+        assert!(matches!(
+            Msg::parse("250%"),
+            Ok(Msg::Move(Direction::Percent(250)))
+        ));
+        assert!(Msg::parse("%").is_err());
+        assert!(Msg::parse("-5%").is_err());
+    }
+
+    #[test]
+    fn test_setting_parse_all() {
+        let settings = Setting::parse_all("snap on caret nibble maybe bogus tildes off");
+        assert!(matches!(
+            settings[..],
+            [
+                Ok(Setting::Snap(true)),
+                Ok(Setting::Caret(None)),
+                Err("expected \"on\" or \"off\""),
+                Err("no such option"),
+                Ok(Setting::Tildes(false)),
+            ]
+        ));
+
+        assert!(matches!(
+            Msg::parse("set snap on"),
+            Ok(Msg::Set(Setting::Snap(true)))
+        ));
+        assert!(matches!(
+            Msg::parse("set snap on tildes off"),
+            Ok(Msg::SetMany(_))
+        ));
+        assert!(Msg::parse("set bogus").is_err());
+        assert!(Msg::parse("set ").is_err());
+    }
+
+    #[test]
+    fn test_msg_parse_all_buffers() {
+        assert!(matches!(Msg::parse("wa"), Ok(Msg::Save)));
+        assert!(matches!(Msg::parse("qa"), Ok(Msg::Quit)));
+        assert!(matches!(Msg::parse("qa!"), Ok(Msg::QuitWithoutSaving)));
+        assert!(matches!(Msg::parse("wqa"), Ok(Msg::SaveAndQuit)));
+        assert!(matches!(Msg::parse("xa"), Ok(Msg::SaveAndQuit)));
+    }
+
+    #[test]
+    fn test_msg_parse_map() {
+        assert!(matches!(
+            Msg::parse("map x :q"),
+            Ok(Msg::Map(Key::Char('x'), ref cmd)) if cmd == "q"
+        ));
+        assert!(matches!(
+            Msg::parse("map Q w out.bin"),
+            Ok(Msg::Map(Key::Char('Q'), ref cmd)) if cmd == "w out.bin"
+        ));
+        assert!(Msg::parse("map x").is_err());
+        assert!(Msg::parse("map xy :q").is_err());
+        assert!(Msg::parse("map x :nosuchcommand").is_err());
+    }
+
+    #[test]
+    fn test_parse_hex_pattern() {
+        assert_eq!(
+            parse_hex_pattern("deadbeef"),
+            Ok(vec![Some(0xde), Some(0xad), Some(0xbe), Some(0xef)])
+        );
+        assert_eq!(
+            parse_hex_pattern(" de ad  BE ef "),
+            Ok(vec![Some(0xde), Some(0xad), Some(0xbe), Some(0xef)])
+        );
+        assert_eq!(parse_hex_pattern(""), Ok(vec![]));
+        assert!(parse_hex_pattern("d").is_err());
+        assert!(parse_hex_pattern("xx").is_err());
+    }
+
+    #[test]
+    fn test_parse_hex_pattern_wildcards() {
+        assert_eq!(
+            parse_hex_pattern("de ?? ef"),
+            Ok(vec![Some(0xde), None, Some(0xef)])
+        );
+        assert_eq!(parse_hex_pattern("de..ef"), parse_hex_pattern("de????ef"));
+        assert!(parse_hex_pattern("d.e").is_err());
+        assert!(parse_hex_pattern("de ?").is_err());
+        assert!(parse_hex_pattern("?a").is_err());
+        assert_eq!(
+            format_hex_pattern(&parse_hex_pattern("DE . ef").unwrap()),
+            "de??ef"
+        );
+    }
+
+    #[test]
+    fn test_setting_parse() {
+        assert!(matches!(Setting::parse("snap on"), Ok(Setting::Snap(true))));
+        assert!(matches!(
+            Setting::parse("snap off"),
+            Ok(Setting::Snap(false))
+        ));
+        assert!(Setting::parse("snap").is_err());
+        assert!(Setting::parse("snap maybe").is_err());
+        assert!(Setting::parse("nosuchoption on").is_err());
+        assert!(matches!(
+            Setting::parse("ignorecase on"),
+            Ok(Setting::IgnoreCase(true))
+        ));
+        assert!(matches!(
+            Setting::parse("showcmd on"),
+            Ok(Setting::ShowCmd(true))
+        ));
+        assert!(matches!(
+            Setting::parse("clipboard osc52"),
+            Ok(Setting::Clipboard(ClipboardKind::Osc52))
+        ));
+        assert!(Setting::parse("clipboard x11").is_err());
+        assert!(matches!(
+            Setting::parse("colorcolumn 4"),
+            Ok(Setting::ColorColumn(Some(4)))
+        ));
+        assert!(matches!(
+            Setting::parse("colorcolumn"),
+            Ok(Setting::ColorColumn(None))
+        ));
+        assert!(Setting::parse("colorcolumn 16").is_err());
+        assert!(matches!(
+            Setting::parse("encoding cp437"),
+            Ok(Setting::Encoding(view::Encoding::Cp437))
+        ));
+        assert!(Setting::parse("encoding ebcdic").is_err());
+        assert!(matches!(Setting::parse("wrap on"), Ok(Setting::Wrap(true))));
+        assert!(matches!(
+            Setting::parse("matchbyte"),
+            Ok(Setting::MatchByte(true))
+        ));
+        assert!(matches!(
+            Setting::parse("nomatchbyte"),
+            Ok(Setting::MatchByte(false))
+        ));
+        assert!(matches!(
+            Setting::parse("signed on"),
+            Ok(Setting::Signed(true))
+        ));
+        assert!(matches!(
+            Setting::parse("virtualedit on"),
+            Ok(Setting::VirtualEdit(true))
+        ));
+        assert!(matches!(
+            Setting::parse("endian big"),
+            Ok(Setting::Endian(controller::Endian::Big))
+        ));
+        assert!(matches!(
+            Setting::parse("nonumber"),
+            Ok(Setting::Number(false))
+        ));
+        assert!(matches!(
+            Setting::parse("number"),
+            Ok(Setting::Number(true))
+        ));
+        assert!(matches!(
+            Setting::parse("number off"),
+            Ok(Setting::Number(false))
+        ));
+        assert!(matches!(Setting::parse("caret"), Ok(Setting::Caret(None))));
+        assert!(matches!(
+            Setting::parse("caret bar"),
+            Ok(Setting::Caret(Some(CaretStyle::Bar)))
+        ));
+        assert!(Setting::parse("caret round").is_err());
+        assert!(matches!(
+            Setting::parse("numberwidth 8"),
+            Ok(Setting::NumberWidth(8))
+        ));
+        assert!(Setting::parse("numberwidth 17").is_err());
+        assert!(Setting::parse("numberwidth -1").is_err());
+    }
 }